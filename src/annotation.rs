@@ -0,0 +1,565 @@
+use crate::reference::ReferenceSequence;
+use crate::{Variant, Variants};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::PathBuf;
+
+type AnnotationTable = HashMap<(String, u64), Vec<String>>;
+
+/// Reads a tab-separated table keyed by its (chrom, pos) columns, skipping
+/// empty lines and `#` comments. The first row of a repeated key is kept.
+fn read_annotation_table(
+    tsv: &PathBuf,
+    (chrom_col, pos_col): (usize, usize),
+) -> Result<AnnotationTable, Box<dyn Error>> {
+    let mut table = HashMap::new();
+    for (line_idx, line) in BufReader::new(File::open(tsv)?).lines().enumerate() {
+        let line = line?;
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<String> = line.split('\t').map(|field| field.to_string()).collect();
+        let key = match (fields.get(chrom_col), fields.get(pos_col)) {
+            (Some(chrom), Some(pos)) => match pos.parse::<u64>() {
+                Ok(pos) => (chrom.clone(), pos),
+                Err(_) => {
+                    return Err(format!("Invalid position `{pos}` in line {}", line_idx + 1).into())
+                }
+            },
+            _ => return Err(format!("Missing key columns in line {}", line_idx + 1).into()),
+        };
+        table.entry(key).or_insert(fields);
+    }
+    Ok(table)
+}
+
+/// Attaches to every variant the fields of the row of a TSV file with the
+/// same chrom and pos, found in the `key_cols` columns (0-based).
+///
+/// The whole table is loaded in memory. Variants without a row get no
+/// annotations, see `Variant::annotations`.
+pub fn annotate_from_tsv<'a>(
+    vars: Variants<'a>,
+    tsv: &PathBuf,
+    key_cols: (usize, usize),
+) -> Result<Variants<'a>, Box<dyn Error>> {
+    let table = read_annotation_table(tsv, key_cols)?;
+    let vars_iter = vars.vars_iter.map(move |var| {
+        let mut var = var?;
+        if let Some(row) = table.get(&(var.chrom.clone(), var.pos)) {
+            var.annotations.clone_from(row);
+        }
+        Ok(var)
+    });
+    Ok(Variants::new(
+        vars.samples,
+        vars_iter,
+        vars.ploidy,
+        vars.ploidy_confident,
+    ))
+}
+
+/// A GFF/GTF feature overlapping a variant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feature {
+    /// The `ID` attribute in GFF3, or the `<kind>_id`, `transcript_id` or
+    /// `gene_id` in GTF; `.` if it has none.
+    pub id: String,
+    /// The feature type: `gene`, `exon` or `CDS`.
+    pub kind: String,
+    /// The `biotype`, `gene_biotype`, `gene_type` or `transcript_biotype`.
+    pub biotype: Option<String>,
+}
+
+impl Feature {
+    pub(crate) fn heap_size(&self) -> usize {
+        self.id.capacity()
+            + self.kind.capacity()
+            + self.biotype.as_ref().map_or(0, String::capacity)
+    }
+}
+
+const GFF_FEATURE_KINDS: [&str; 3] = ["gene", "exon", "CDS"];
+const GFF_BIOTYPE_KEYS: [&str; 4] = ["biotype", "gene_biotype", "gene_type", "transcript_biotype"];
+
+/// Static interval tree over closed intervals, an implicit balanced binary
+/// tree over the intervals sorted by start in which every node keeps the
+/// largest end of its subtree.
+pub(crate) struct IntervalTree<T> {
+    intervals: Vec<(u64, u64, T)>,
+    max_ends: Vec<u64>,
+}
+
+impl<T> IntervalTree<T> {
+    pub(crate) fn new(mut intervals: Vec<(u64, u64, T)>) -> IntervalTree<T> {
+        intervals.sort_unstable_by_key(|(start, end, _)| (*start, *end));
+        let mut tree = IntervalTree {
+            max_ends: vec![0; intervals.len()],
+            intervals,
+        };
+        tree.build_max_ends(0, tree.intervals.len());
+        tree
+    }
+
+    fn build_max_ends(&mut self, lo: usize, hi: usize) -> u64 {
+        if lo >= hi {
+            return 0;
+        }
+        let mid = (lo + hi) / 2;
+        let max_end = self.intervals[mid]
+            .1
+            .max(self.build_max_ends(lo, mid))
+            .max(self.build_max_ends(mid + 1, hi));
+        self.max_ends[mid] = max_end;
+        max_end
+    }
+
+    /// The items of the intervals that overlap `[start, end]`.
+    pub(crate) fn overlapping(&self, start: u64, end: u64) -> Vec<&T> {
+        let mut found = Vec::new();
+        self.collect_overlapping(0, self.intervals.len(), (start, end), &mut found);
+        found
+    }
+
+    fn collect_overlapping<'a>(
+        &'a self,
+        lo: usize,
+        hi: usize,
+        (start, end): (u64, u64),
+        found: &mut Vec<&'a T>,
+    ) {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        if self.max_ends[mid] < start {
+            return;
+        }
+        self.collect_overlapping(lo, mid, (start, end), found);
+        let (interval_start, interval_end, item) = &self.intervals[mid];
+        if *interval_start <= end {
+            if *interval_end >= start {
+                found.push(item);
+            }
+            self.collect_overlapping(mid + 1, hi, (start, end), found);
+        }
+    }
+}
+
+/// The attributes of a GFF3 (`key=value;`) or GTF (`key "value";`) line.
+fn parse_gff_attributes(attributes: &str) -> HashMap<&str, &str> {
+    attributes
+        .split(';')
+        .map(|item| item.trim())
+        .filter_map(|item| item.split_once('=').or_else(|| item.split_once(' ')))
+        .map(|(key, value)| (key.trim(), value.trim().trim_matches('"')))
+        .collect()
+}
+
+/// Reads the gene, exon and CDS features of a GFF3 or GTF file, per chromosome.
+fn read_gff_features(
+    gff: &PathBuf,
+) -> Result<HashMap<String, IntervalTree<Feature>>, Box<dyn Error>> {
+    let mut features: HashMap<String, Vec<(u64, u64, Feature)>> = HashMap::new();
+    for (line_idx, line) in BufReader::new(File::open(gff)?).lines().enumerate() {
+        let line = line?;
+        if line.starts_with("##FASTA") {
+            break;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 9 {
+            return Err(format!("Expected 9 columns in GFF line {}", line_idx + 1).into());
+        }
+        if !GFF_FEATURE_KINDS.contains(&fields[2]) {
+            continue;
+        }
+        let (start, end) = match (fields[3].parse::<u64>(), fields[4].parse::<u64>()) {
+            (Ok(start), Ok(end)) => (start, end),
+            _ => return Err(format!("Invalid coordinates in GFF line {}", line_idx + 1).into()),
+        };
+        let attributes = parse_gff_attributes(fields[8]);
+        let kind_id = format!("{}_id", fields[2].to_lowercase());
+        let id = ["ID", kind_id.as_str(), "transcript_id", "gene_id"]
+            .iter()
+            .find_map(|key| attributes.get(key))
+            .unwrap_or(&".");
+        let biotype = GFF_BIOTYPE_KEYS
+            .iter()
+            .find_map(|key| attributes.get(key))
+            .map(|biotype| biotype.to_string());
+        let feature = Feature {
+            id: id.to_string(),
+            kind: fields[2].to_string(),
+            biotype,
+        };
+        features
+            .entry(fields[0].to_string())
+            .or_default()
+            .push((start, end, feature));
+    }
+    Ok(features
+        .into_iter()
+        .map(|(chrom, features)| (chrom, IntervalTree::new(features)))
+        .collect())
+}
+
+/// Attaches to every variant the gene, exon and CDS features of a GFF3 or
+/// GTF file that overlap its reference span, from POS to `Variant::end`.
+///
+/// All the features are loaded in memory, in an interval tree per
+/// chromosome. Features are attached in order of start, see
+/// `Variant::features`.
+pub fn annotate_with_gff<'a>(
+    vars: Variants<'a>,
+    gff: &PathBuf,
+) -> Result<Variants<'a>, Box<dyn Error>> {
+    let features = read_gff_features(gff)?;
+    let vars_iter = vars.vars_iter.map(move |var| {
+        let mut var: Variant = var?;
+        if let Some(tree) = features.get(&var.chrom) {
+            var.features = tree
+                .overlapping(var.pos, var.end())
+                .into_iter()
+                .cloned()
+                .collect();
+        }
+        Ok(var)
+    });
+    Ok(Variants::new(
+        vars.samples,
+        vars_iter,
+        vars.ploidy,
+        vars.ploidy_confident,
+    ))
+}
+
+/// The strand of a transcript.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strand {
+    Forward,
+    Reverse,
+}
+
+/// A transcript, with 1-based closed coordinates on the forward strand.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    pub id: String,
+    pub chrom: String,
+    pub strand: Strand,
+    /// Sorted by position.
+    pub exons: Vec<(u64, u64)>,
+    /// From the first base of the start codon to the last of the stop codon,
+    /// `None` in non-coding transcripts.
+    pub cds: Option<(u64, u64)>,
+}
+
+/// The effect of a variant on a transcript.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Consequence {
+    /// The codon is translated into the same amino acid.
+    Synonymous,
+    /// The codon is translated into another amino acid, in one letter codes.
+    Missense { ref_aa: char, alt_aa: char },
+    /// An amino acid codon becomes a stop codon.
+    Nonsense,
+    /// The stop codon becomes an amino acid codon.
+    StopLost,
+    /// Within 3 exonic or 8 intronic bases of a splice site.
+    SpliceRegion,
+    /// Outside the coding sequence of the transcript.
+    NonCoding,
+    /// Not a biallelic SNP, or a coding SNP in a transcript with more than
+    /// one exon, which are not classified yet.
+    Unsupported,
+}
+
+/// The standard genetic code, with the codons in TCAG order.
+const GENETIC_CODE: &[u8; 64] = b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+
+fn translate(codon: &[u8]) -> Option<char> {
+    let mut idx = 0;
+    for base in codon {
+        let base_idx = match base.to_ascii_uppercase() {
+            b'T' => 0,
+            b'C' => 1,
+            b'A' => 2,
+            b'G' => 3,
+            _ => return None,
+        };
+        idx = idx * 4 + base_idx;
+    }
+    Some(GENETIC_CODE[idx] as char)
+}
+
+fn complement(base: u8) -> u8 {
+    match base.to_ascii_uppercase() {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        other => other,
+    }
+}
+
+/// True if `pos` is within 3 exonic or 8 intronic bases of a splice site.
+fn in_splice_region(pos: u64, exons: &[(u64, u64)]) -> bool {
+    let mut introns = exons.windows(2).map(|pair| (pair[0].1, pair[1].0));
+    introns.any(|(exon_end, next_exon_start)| {
+        (exon_end.saturating_sub(2)..=exon_end + 8).contains(&pos)
+            || (next_exon_start.saturating_sub(8)..=next_exon_start + 2).contains(&pos)
+    })
+}
+
+/// Classifies the effect of a biallelic SNP on a transcript by translating
+/// the reference and alternative codons.
+///
+/// Only the SNPs in the coding sequence of single exon transcripts are
+/// translated, the rest are `Unsupported` unless they are in a splice region.
+pub fn predict_consequence(
+    var: &Variant,
+    transcript: &Transcript,
+    reference: &dyn ReferenceSequence,
+) -> io::Result<Consequence> {
+    let mut alt_base = match var.alleles.as_slice() {
+        [ref_allele, alt_allele] if ref_allele.len() == 1 && alt_allele.len() == 1 => {
+            alt_allele.as_bytes()[0]
+        }
+        _ => return Ok(Consequence::Unsupported),
+    };
+    if in_splice_region(var.pos, &transcript.exons) {
+        return Ok(Consequence::SpliceRegion);
+    }
+    let (cds_start, cds_end) = match transcript.cds {
+        Some((cds_start, cds_end)) if (cds_start..=cds_end).contains(&var.pos) => {
+            (cds_start, cds_end)
+        }
+        _ => return Ok(Consequence::NonCoding),
+    };
+    if transcript.exons.len() != 1 {
+        return Ok(Consequence::Unsupported);
+    }
+    let (codon_start, base_idx) = match transcript.strand {
+        Strand::Forward => {
+            let offset = var.pos - cds_start;
+            (var.pos - offset % 3, offset % 3)
+        }
+        Strand::Reverse => {
+            let offset = cds_end - var.pos;
+            (var.pos + offset % 3 - 2, offset % 3)
+        }
+    };
+    let mut ref_codon = reference
+        .sequence(&transcript.chrom, codon_start, 3)?
+        .into_bytes();
+    if transcript.strand == Strand::Reverse {
+        ref_codon = ref_codon
+            .iter()
+            .rev()
+            .map(|base| complement(*base))
+            .collect();
+        alt_base = complement(alt_base);
+    }
+    let mut alt_codon = ref_codon.clone();
+    alt_codon[base_idx as usize] = alt_base;
+
+    let (ref_aa, alt_aa) = match (translate(&ref_codon), translate(&alt_codon)) {
+        (Some(ref_aa), Some(alt_aa)) => (ref_aa, alt_aa),
+        _ => return Ok(Consequence::Unsupported),
+    };
+    Ok(match (ref_aa, alt_aa) {
+        (ref_aa, alt_aa) if ref_aa == alt_aa => Consequence::Synonymous,
+        (_, '*') => Consequence::Nonsense,
+        ('*', _) => Consequence::StopLost,
+        (ref_aa, alt_aa) => Consequence::Missense { ref_aa, alt_aa },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{parse_vcf_buffer_str, VCF_45};
+
+    #[test]
+    fn annotate_and_filter() {
+        let tsv = std::env::temp_dir().join("nei_rs_annotations.tsv");
+        std::fs::write(
+            &tsv,
+            "#gene\tchrom\tpos\tcadd\nGENE1\t20\t14370\t12.5\nGENE2\t20\t1230237\t3.1\nGENE3\t21\t14370\t1.0\n",
+        )
+        .unwrap();
+        let vars = annotate_from_tsv(parse_vcf_buffer_str(VCF_45), &tsv, (1, 2)).unwrap();
+        let vars: Vec<Variant> = vars.vars_iter.map(|var| var.unwrap()).collect();
+        assert_eq!(vars[0].annotations(), ["GENE1", "20", "14370", "12.5"]);
+        assert!(vars[1].annotations().is_empty());
+        assert_eq!(vars[3].annotations()[0], "GENE2");
+
+        let vars = annotate_from_tsv(parse_vcf_buffer_str(VCF_45), &tsv, (1, 2))
+            .unwrap()
+            .filter_variants(|var| !var.annotations().is_empty());
+        let positions: Vec<u64> = vars.vars_iter.map(|var| var.unwrap().pos).collect();
+        assert_eq!(positions, vec![14370, 1230237]);
+
+        assert!(annotate_from_tsv(parse_vcf_buffer_str(VCF_45), &tsv, (1, 0)).is_err());
+    }
+
+    #[test]
+    fn interval_tree() {
+        let intervals = vec![(10, 20, 'a'), (15, 15, 'b'), (1, 100, 'c'), (30, 40, 'd')];
+        let tree = IntervalTree::new(intervals);
+        assert_eq!(tree.overlapping(15, 15), vec![&'c', &'a', &'b']);
+        assert_eq!(tree.overlapping(21, 29), vec![&'c']);
+        assert_eq!(tree.overlapping(40, 200), vec![&'c', &'d']);
+        assert!(tree.overlapping(101, 200).is_empty());
+    }
+
+    #[test]
+    fn gff_features() {
+        let gff = std::env::temp_dir().join("nei_rs_features.gff3");
+        std::fs::write(
+            &gff,
+            "##gff-version 3
+20\t.\tgene\t10000\t20000\t.\t+\t.\tID=gene1;biotype=protein_coding
+20\t.\tmRNA\t10000\t20000\t.\t+\t.\tID=tx1;Parent=gene1
+20\t.\texon\t14000\t14370\t.\t+\t.\tID=exon1;Parent=tx1
+20\t.\tCDS\t14100\t14370\t.\t+\t0\tID=cds1;Parent=tx1
+20\t.\tgene\t1234568\t1234600\t.\t-\t.\tID=gene2;biotype=lncRNA
+",
+        )
+        .unwrap();
+        let vars = annotate_with_gff(parse_vcf_buffer_str(VCF_45), &gff).unwrap();
+        let vars: Vec<Variant> = vars.vars_iter.map(|var| var.unwrap()).collect();
+        let ids = |var: &Variant| -> Vec<String> {
+            var.features()
+                .iter()
+                .map(|feature| feature.id.clone())
+                .collect()
+        };
+        assert_eq!(ids(&vars[0]), vec!["gene1", "exon1", "cds1"]);
+        assert_eq!(
+            vars[0].features()[0].biotype.as_deref(),
+            Some("protein_coding")
+        );
+        assert_eq!(ids(&vars[1]), vec!["gene1"]);
+        assert!(vars[2].features().is_empty());
+        // The REF GTC of the microsatellite spans up to 1234569.
+        assert_eq!(vars[4].features()[0].kind, "gene");
+        assert_eq!(ids(&vars[4]), vec!["gene2"]);
+
+        let gtf = std::env::temp_dir().join("nei_rs_features.gtf");
+        std::fs::write(
+            &gtf,
+            "20\tsrc\texon\t14370\t14370\t.\t+\t.\tgene_id \"g1\"; transcript_id \"t1\"; exon_id \"e1\"; gene_biotype \"miRNA\";\n",
+        )
+        .unwrap();
+        let vars = annotate_with_gff(parse_vcf_buffer_str(VCF_45), &gtf).unwrap();
+        let var = vars.vars_iter.map(|var| var.unwrap()).next().unwrap();
+        assert_eq!(
+            var.features(),
+            [Feature {
+                id: "e1".to_string(),
+                kind: "exon".to_string(),
+                biotype: Some("miRNA".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn consequences() {
+        // A CDS on the forward strand: ATG GCT TGG TAA, Met Ala Trp Stop,
+        // and its reverse complement at 101..112 on the reverse strand.
+        let cds = "ATGGCTTGGTAA";
+        let reverse_cds: String = cds
+            .bytes()
+            .rev()
+            .map(|base| complement(base) as char)
+            .collect();
+        let seq = format!(
+            "{}{cds}{}{reverse_cds}{}",
+            "c".repeat(10),
+            "c".repeat(78),
+            "c".repeat(10)
+        );
+        let reference = HashMap::from([("1".to_string(), seq)]);
+        let forward = Transcript {
+            id: "tx1".to_string(),
+            chrom: "1".to_string(),
+            strand: Strand::Forward,
+            exons: vec![(1, 60)],
+            cds: Some((11, 22)),
+        };
+        let reverse = Transcript {
+            id: "tx2".to_string(),
+            strand: Strand::Reverse,
+            exons: vec![(90, 130)],
+            cds: Some((101, 112)),
+            ..forward.clone()
+        };
+        let consequence = |pos: u64,
+                           ref_allele: &str,
+                           alt_allele: &str,
+                           transcript: &Transcript| {
+            let vcf = format!(
+                "##fileformat=VCFv4.5\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\n1\t{pos}\t.\t{ref_allele}\t{alt_allele}\t.\tPASS\t.\tGT\t0/1"
+            );
+            let var = crate::tests::parse_vcf_str(&vcf).remove(0);
+            predict_consequence(&var, transcript, &reference).unwrap()
+        };
+
+        // GCT -> GCC, Ala -> Ala
+        assert_eq!(consequence(16, "T", "C", &forward), Consequence::Synonymous);
+        // GCT -> ACT, Ala -> Thr
+        assert_eq!(
+            consequence(14, "G", "A", &forward),
+            Consequence::Missense {
+                ref_aa: 'A',
+                alt_aa: 'T'
+            }
+        );
+        // TGG -> TGA, Trp -> Stop
+        assert_eq!(consequence(19, "G", "A", &forward), Consequence::Nonsense);
+        // TAA -> CAA, Stop -> Gln
+        assert_eq!(consequence(20, "T", "C", &forward), Consequence::StopLost);
+        assert_eq!(consequence(30, "C", "A", &forward), Consequence::NonCoding);
+        assert_eq!(
+            consequence(16, "T", "CA", &forward),
+            Consequence::Unsupported
+        );
+
+        // On the reverse strand the codon TGG is CCA at 104..106.
+        assert_eq!(consequence(104, "C", "T", &reverse), Consequence::Nonsense);
+        // GCT is AGC at 107..109, GCT -> GCC
+        assert_eq!(
+            consequence(107, "A", "G", &reverse),
+            Consequence::Synonymous
+        );
+        assert_eq!(
+            consequence(109, "C", "T", &reverse),
+            Consequence::Missense {
+                ref_aa: 'A',
+                alt_aa: 'T'
+            }
+        );
+
+        let spliced = Transcript {
+            exons: vec![(1, 14), (40, 60)],
+            ..forward.clone()
+        };
+        assert_eq!(
+            consequence(20, "T", "C", &spliced),
+            Consequence::SpliceRegion
+        );
+        assert_eq!(
+            consequence(13, "C", "A", &spliced),
+            Consequence::SpliceRegion
+        );
+        assert_eq!(
+            consequence(11, "A", "G", &spliced),
+            Consequence::Unsupported
+        );
+    }
+}
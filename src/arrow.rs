@@ -0,0 +1,170 @@
+//! Conversion of the variants into Arrow record batches.
+//!
+//! Every batch has the columns:
+//!
+//! | column      | type                      |
+//! |-------------|---------------------------|
+//! | `chrom`     | `Utf8`                    |
+//! | `pos`       | `UInt64`                  |
+//! | `id`        | `Utf8`                    |
+//! | `ref`       | `Utf8`                    |
+//! | `alt`       | `List<Utf8>`              |
+//! | `qual`      | `Float64`                 |
+//! | `genotypes` | `List<List<Int16>>`       |
+//!
+//! `genotypes` holds, for every sample, the list of its allele indexes. Missing
+//! alleles are stored as -1, like in `Variant`, not as Arrow nulls. With the
+//! `i8` feature its type is `List<List<Int8>>`.
+
+use crate::{VCFParseError, Variant, Variants};
+#[cfg(not(feature = "i8"))]
+use arrow_array::builder::Int16Builder as AlleleBuilder;
+#[cfg(feature = "i8")]
+use arrow_array::builder::Int8Builder as AlleleBuilder;
+use arrow_array::builder::{Float64Builder, ListBuilder, StringBuilder, UInt64Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use std::sync::Arc;
+
+struct RecordBatchBuilder {
+    chrom: StringBuilder,
+    pos: UInt64Builder,
+    id: StringBuilder,
+    ref_allele: StringBuilder,
+    alt: ListBuilder<StringBuilder>,
+    qual: Float64Builder,
+    genotypes: ListBuilder<ListBuilder<AlleleBuilder>>,
+}
+
+impl RecordBatchBuilder {
+    fn new() -> RecordBatchBuilder {
+        RecordBatchBuilder {
+            chrom: StringBuilder::new(),
+            pos: UInt64Builder::new(),
+            id: StringBuilder::new(),
+            ref_allele: StringBuilder::new(),
+            alt: ListBuilder::new(StringBuilder::new()),
+            qual: Float64Builder::new(),
+            genotypes: ListBuilder::new(ListBuilder::new(AlleleBuilder::new())),
+        }
+    }
+
+    fn append(&mut self, var: &Variant) {
+        self.chrom.append_value(&var.chrom);
+        self.pos.append_value(var.pos);
+        self.id.append_value(&var.id);
+        self.ref_allele.append_value(&var.alleles[0]);
+        for alt in &var.alleles[1..] {
+            self.alt.values().append_value(alt);
+        }
+        self.alt.append(true);
+        self.qual.append_value(var.qual);
+        for gt in &var.gts {
+            self.genotypes.values().values().append_slice(gt);
+            self.genotypes.values().append(true);
+        }
+        self.genotypes.append(true);
+    }
+
+    fn finish(&mut self) -> RecordBatch {
+        let columns: Vec<(&str, ArrayRef)> = vec![
+            ("chrom", Arc::new(self.chrom.finish())),
+            ("pos", Arc::new(self.pos.finish())),
+            ("id", Arc::new(self.id.finish())),
+            ("ref", Arc::new(self.ref_allele.finish())),
+            ("alt", Arc::new(self.alt.finish())),
+            ("qual", Arc::new(self.qual.finish())),
+            ("genotypes", Arc::new(self.genotypes.finish())),
+        ];
+        RecordBatch::try_from_iter(columns).expect("All the columns have the same length")
+    }
+}
+
+/// Buffers all the variants into a single record batch.
+pub fn to_arrow(vars: &mut Variants) -> Result<RecordBatch, VCFParseError> {
+    let mut builder = RecordBatchBuilder::new();
+    for var in vars.vars_iter.by_ref() {
+        builder.append(&var?);
+    }
+    Ok(builder.finish())
+}
+
+/// Yields record batches of up to `batch_size` variants.
+///
+/// A parse error is yielded in place of the batch it was found in, and the
+/// variants of that batch read before it are dropped.
+pub fn to_arrow_batches<'a>(
+    mut vars: Variants<'a>,
+    batch_size: usize,
+) -> impl Iterator<Item = Result<RecordBatch, VCFParseError>> + 'a {
+    let mut builder = RecordBatchBuilder::new();
+    std::iter::from_fn(move || {
+        let mut n_vars = 0;
+        for var in vars.vars_iter.by_ref() {
+            match var {
+                Ok(var) => builder.append(&var),
+                Err(e) => {
+                    builder.finish();
+                    return Some(Err(e));
+                }
+            }
+            n_vars += 1;
+            if n_vars == batch_size {
+                break;
+            }
+        }
+        if n_vars == 0 {
+            None
+        } else {
+            Some(Ok(builder.finish()))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{parse_vcf_buffer_str, VCF_45, VCF_MISSING};
+    use arrow_array::cast::AsArray;
+    #[cfg(not(feature = "i8"))]
+    use arrow_array::types::Int16Type as AlleleType;
+    #[cfg(feature = "i8")]
+    use arrow_array::types::Int8Type as AlleleType;
+    use arrow_array::types::UInt64Type;
+
+    #[test]
+    fn record_batch() {
+        let mut vars = parse_vcf_buffer_str(VCF_MISSING);
+        let batch = to_arrow(&mut vars).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.num_columns(), 7);
+
+        let pos = batch.column(1).as_primitive::<UInt64Type>();
+        assert_eq!(pos.values(), &[100, 200]);
+
+        let gts = batch.column(6).as_list::<i32>();
+        let first_site = gts.value(0);
+        let second_sample = first_site.as_list::<i32>().value(1);
+        assert_eq!(
+            second_sample.as_primitive::<AlleleType>().values(),
+            &[-1, -1]
+        );
+    }
+
+    #[test]
+    fn batches() {
+        let vars = parse_vcf_buffer_str(VCF_45);
+        let n_rows: Vec<usize> = to_arrow_batches(vars, 4)
+            .map(|batch| batch.unwrap().num_rows())
+            .collect();
+        assert_eq!(n_rows, vec![4, 2]);
+
+        let vcf = VCF_45.replace("\n20\t17330\t", "\n20\tpos\t");
+        let batches: Vec<Result<RecordBatch, VCFParseError>> =
+            to_arrow_batches(parse_vcf_buffer_str(&vcf), 4).collect();
+        assert_eq!(batches.len(), 2);
+        assert!(matches!(batches[0], Err(VCFParseError::PosNotInt(_))));
+        let batch = batches[1].as_ref().unwrap();
+        let pos = batch.column(1).as_primitive::<UInt64Type>();
+        assert_eq!(pos.values(), &[1110696, 1230237, 1234567, 1234567]);
+    }
+}
@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+/// The order of the chromosomes in a sorted file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ChromOrder {
+    /// The order of the `##contig` lines.
+    #[default]
+    Header,
+    /// The order of the list.
+    List(Vec<String>),
+    /// Numbers first, in numeric order, and then the rest alphabetically,
+    /// ignoring a `chr` prefix: `chr2` goes before `chr10`, and `chrX` after.
+    Natural,
+}
+
+/// Sort key of a chromosome, only comparable with the keys of the same
+/// `ChromRanks`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ChromKey {
+    Rank(usize),
+    Natural {
+        is_named: bool,
+        number: u64,
+        name: String,
+    },
+}
+
+/// Ranks the chromosomes in a `ChromOrder`.
+///
+/// With `Header` and `List`, chromosomes not in the order are ranked after
+/// the rest in the order in which they are first seen.
+pub(crate) struct ChromRanks {
+    natural: bool,
+    ranks: HashMap<String, usize>,
+}
+
+impl ChromRanks {
+    /// `contigs` are those of the headers, only used by `ChromOrder::Header`.
+    pub(crate) fn new<'a>(
+        order: &ChromOrder,
+        contigs: impl IntoIterator<Item = &'a str>,
+    ) -> ChromRanks {
+        let mut chrom_ranks = ChromRanks {
+            natural: *order == ChromOrder::Natural,
+            ranks: HashMap::new(),
+        };
+        match order {
+            ChromOrder::Header => contigs.into_iter().for_each(|chrom| {
+                chrom_ranks.rank(chrom);
+            }),
+            ChromOrder::List(chroms) => chroms.iter().for_each(|chrom| {
+                chrom_ranks.rank(chrom);
+            }),
+            ChromOrder::Natural => {}
+        }
+        chrom_ranks
+    }
+
+    fn rank(&mut self, chrom: &str) -> usize {
+        let next_rank = self.ranks.len();
+        *self.ranks.entry(chrom.to_string()).or_insert(next_rank)
+    }
+
+    pub(crate) fn key(&mut self, chrom: &str) -> ChromKey {
+        if !self.natural {
+            return ChromKey::Rank(self.rank(chrom));
+        }
+        let name = ["chr", "Chr", "CHR"]
+            .iter()
+            .find_map(|prefix| chrom.strip_prefix(prefix))
+            .unwrap_or(chrom);
+        match name.parse() {
+            Ok(number) => ChromKey::Natural {
+                is_named: false,
+                number,
+                name: String::new(),
+            },
+            Err(_) => ChromKey::Natural {
+                is_named: true,
+                number: 0,
+                name: name.to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(order: &ChromOrder, contigs: &[&str], chroms: &[&str]) -> Vec<String> {
+        let mut chrom_ranks = ChromRanks::new(order, contigs.iter().copied());
+        let mut chroms: Vec<String> = chroms.iter().map(|chrom| chrom.to_string()).collect();
+        chroms.sort_by_cached_key(|chrom| chrom_ranks.key(chrom));
+        chroms
+    }
+
+    #[test]
+    fn chrom_orders() {
+        let chroms = ["chr10", "chrX", "chr2", "chr1"];
+        assert_eq!(
+            sorted(&ChromOrder::Natural, &[], &chroms),
+            vec!["chr1", "chr2", "chr10", "chrX"]
+        );
+        assert_eq!(
+            sorted(&ChromOrder::Header, &["chrX", "chr2"], &chroms),
+            vec!["chrX", "chr2", "chr10", "chr1"]
+        );
+        let order = ChromOrder::List(vec!["chr1".to_string(), "chr10".to_string()]);
+        assert_eq!(
+            sorted(&order, &["chrX"], &chroms),
+            vec!["chr1", "chr10", "chrX", "chr2"]
+        );
+    }
+}
@@ -0,0 +1,494 @@
+use crate::chrom_order::ChromRanks;
+use crate::stats::population_allele_freqs;
+use crate::{
+    read_vcf_file, read_vcf_header, ChromOrder, VCFParseError, Variant, Variants, VariantsIter,
+    MISSING_ALLELE,
+};
+use std::error::Error;
+use std::path::PathBuf;
+
+/// Chromosome ranks for merging two sorted files, with the contigs of both
+/// headers for `ChromOrder::Header`.
+fn chrom_ranks_from_files(
+    a: &PathBuf,
+    b: &PathBuf,
+    order: &ChromOrder,
+) -> Result<ChromRanks, Box<dyn Error>> {
+    let contigs: Vec<String> = [read_vcf_header(a)?, read_vcf_header(b)?]
+        .into_iter()
+        .flat_map(|header| header.contigs)
+        .map(|contig| contig.id)
+        .collect();
+    Ok(ChromRanks::new(order, contigs.iter().map(|id| id.as_str())))
+}
+
+fn take_site(
+    vars: &mut VariantsIter,
+    chrom: &str,
+    pos: u64,
+) -> Result<Vec<Variant>, VCFParseError> {
+    let mut site = Vec::new();
+    while let Some(Ok(var)) = vars.peek() {
+        if var.chrom != chrom || var.pos != pos {
+            break;
+        }
+        site.push(vars.next().unwrap()?);
+    }
+    Ok(site)
+}
+
+/// Merge-join of two coordinate-sorted streams, yielding the pairs of records
+/// that are the same site, see `Variant::same_site`.
+pub(crate) struct SharedSites<'a> {
+    a: VariantsIter<'a>,
+    b: VariantsIter<'a>,
+    chrom_ranks: ChromRanks,
+    match_alts: bool,
+    pending: std::vec::IntoIter<(Variant, Variant)>,
+}
+
+impl<'a> SharedSites<'a> {
+    fn new(
+        a: Variants<'a>,
+        b: Variants<'a>,
+        chrom_ranks: ChromRanks,
+        match_alts: bool,
+    ) -> SharedSites<'a> {
+        SharedSites {
+            a: a.vars_iter,
+            b: b.vars_iter,
+            chrom_ranks,
+            match_alts,
+            pending: Vec::new().into_iter(),
+        }
+    }
+
+    /// Opens both files, with the chromosome order taken from their headers,
+    /// matching the ALTs.
+    pub(crate) fn from_files(
+        a: &PathBuf,
+        b: &PathBuf,
+    ) -> Result<SharedSites<'static>, Box<dyn Error>> {
+        let chrom_ranks = chrom_ranks_from_files(a, b, &ChromOrder::Header)?;
+        Ok(SharedSites::new(
+            read_vcf_file(a)?,
+            read_vcf_file(b)?,
+            chrom_ranks,
+            true,
+        ))
+    }
+}
+
+impl Iterator for SharedSites<'_> {
+    type Item = Result<(Variant, Variant), VCFParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(pair) = self.pending.next() {
+                return Some(Ok(pair));
+            }
+            let (var_a, var_b) = match (self.a.peek(), self.b.peek()) {
+                (None, _) | (_, None) => return None,
+                (Some(Err(_)), _) => return Some(Err(self.a.next()?.unwrap_err())),
+                (_, Some(Err(_))) => return Some(Err(self.b.next()?.unwrap_err())),
+                (Some(Ok(var_a)), Some(Ok(var_b))) => (var_a, var_b),
+            };
+            let key_a = (self.chrom_ranks.key(&var_a.chrom), var_a.pos);
+            let key_b = (self.chrom_ranks.key(&var_b.chrom), var_b.pos);
+            if key_a < key_b {
+                self.a.next();
+                continue;
+            } else if key_b < key_a {
+                self.b.next();
+                continue;
+            }
+
+            let (chrom, pos) = (var_a.chrom.clone(), var_a.pos);
+            let site_a = match take_site(&mut self.a, &chrom, pos) {
+                Ok(site) => site,
+                Err(e) => return Some(Err(e)),
+            };
+            let mut site_b: Vec<Option<Variant>> = match take_site(&mut self.b, &chrom, pos) {
+                Ok(site) => site.into_iter().map(Some).collect(),
+                Err(e) => return Some(Err(e)),
+            };
+            let mut pairs = Vec::new();
+            for var_a in site_a {
+                let matching = site_b.iter_mut().find(|var_b| {
+                    var_b
+                        .as_ref()
+                        .is_some_and(|var_b| var_a.same_site(var_b, self.match_alts))
+                });
+                if let Some(var_b) = matching {
+                    pairs.push((var_a, var_b.take().unwrap()));
+                }
+            }
+            self.pending = pairs.into_iter();
+        }
+    }
+}
+
+/// The records of `a` that are also found in `b`, like `bcftools isec`.
+///
+/// Both files must be sorted, with the chromosomes in `order`. Records are
+/// matched by chrom, pos and REF, and also by their set of ALTs if
+/// `match_alts` is true, see `Variant::same_site`.
+pub fn intersect_vcfs(
+    a: &PathBuf,
+    b: &PathBuf,
+    match_alts: bool,
+    order: &ChromOrder,
+) -> Result<Variants<'static>, Box<dyn Error>> {
+    let chrom_ranks = chrom_ranks_from_files(a, b, order)?;
+    let vars_a = read_vcf_file(a)?;
+    let (samples, ploidy, ploidy_confident) = (
+        vars_a.samples.clone(),
+        vars_a.ploidy,
+        vars_a.ploidy_confident,
+    );
+    let shared_sites = SharedSites::new(vars_a, read_vcf_file(b)?, chrom_ranks, match_alts);
+    let vars_iter = shared_sites.map(|pair| pair.map(|(var_a, _)| var_a));
+    Ok(Variants::new(samples, vars_iter, ploidy, ploidy_confident))
+}
+
+/// Genotype comparison counts between a test and a truth call set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConcordanceCounts {
+    /// Genotypes called in both files.
+    pub n_compared: u64,
+    pub n_matching: u64,
+    /// Compared genotypes with a non-reference allele in either file.
+    pub n_non_ref_compared: u64,
+    pub n_non_ref_matching: u64,
+    pub n_missing_a: u64,
+    pub n_missing_b: u64,
+    /// Shared sites, the denominator of the missing rates.
+    pub n_sites: u64,
+}
+
+impl ConcordanceCounts {
+    pub fn concordance(&self) -> f64 {
+        self.n_matching as f64 / self.n_compared as f64
+    }
+
+    pub fn non_reference_concordance(&self) -> f64 {
+        self.n_non_ref_matching as f64 / self.n_non_ref_compared as f64
+    }
+
+    pub fn missing_rate_a(&self) -> f64 {
+        self.n_missing_a as f64 / self.n_sites as f64
+    }
+
+    pub fn missing_rate_b(&self) -> f64 {
+        self.n_missing_b as f64 / self.n_sites as f64
+    }
+
+    fn add(&mut self, other: &ConcordanceCounts) {
+        self.n_compared += other.n_compared;
+        self.n_matching += other.n_matching;
+        self.n_non_ref_compared += other.n_non_ref_compared;
+        self.n_non_ref_matching += other.n_non_ref_matching;
+        self.n_missing_a += other.n_missing_a;
+        self.n_missing_b += other.n_missing_b;
+        self.n_sites += other.n_sites;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConcordanceReport {
+    /// The samples present in both files.
+    pub samples: Vec<String>,
+    pub per_sample: Vec<ConcordanceCounts>,
+    pub overall: ConcordanceCounts,
+    pub n_shared_sites: u64,
+}
+
+/// The alleles of a genotype as sorted strings, None if any is missing.
+fn genotype_alleles(var: &Variant, sample_idx: usize) -> Option<Vec<&str>> {
+    let gt = &var.gts[sample_idx];
+    if gt.contains(&MISSING_ALLELE) {
+        return None;
+    }
+    let mut alleles: Vec<&str> = gt
+        .iter()
+        .map(|allele| var.alleles[*allele as usize].as_str())
+        .collect();
+    alleles.sort_unstable();
+    Some(alleles)
+}
+
+type SharedSamples = (Vec<String>, Vec<(usize, usize)>);
+
+/// The samples found in both files, in the order of `a`, with their indexes
+/// in `a` and `b`.
+fn shared_samples(a: &PathBuf, b: &PathBuf) -> Result<SharedSamples, Box<dyn Error>> {
+    let samples_b = read_vcf_header(b)?.samples;
+    let mut samples = Vec::new();
+    let mut sample_pairs = Vec::new();
+    for (idx_a, sample) in read_vcf_header(a)?.samples.into_iter().enumerate() {
+        if let Some(idx_b) = samples_b.iter().position(|sample_b| *sample_b == sample) {
+            samples.push(sample);
+            sample_pairs.push((idx_a, idx_b));
+        }
+    }
+    Ok((samples, sample_pairs))
+}
+
+type FreqDelta = (String, u64, f64);
+
+/// Change of the ALT frequency from `t1` to `t2`, `t2 - t1`, at the biallelic
+/// sites found in both files, as (chrom, pos, delta).
+///
+/// Sites are matched by chrom, pos, REF and ALT, and the frequencies are
+/// computed over the samples found in both files. The delta is NaN if every
+/// allele of those samples is missing in either file. Both files must be
+/// coordinate-sorted with the same chromosome order.
+pub fn allele_freq_delta(
+    t1: &PathBuf,
+    t2: &PathBuf,
+) -> Result<impl Iterator<Item = Result<FreqDelta, VCFParseError>>, Box<dyn Error>> {
+    let (_, sample_pairs) = shared_samples(t1, t2)?;
+    let (samples_t1, samples_t2): (Vec<usize>, Vec<usize>) = sample_pairs.into_iter().unzip();
+    let alt_freq = |var: &Variant, samples: &[usize]| {
+        population_allele_freqs(var, samples)
+            .get(1)
+            .copied()
+            .unwrap_or(f64::NAN)
+    };
+    Ok(SharedSites::from_files(t1, t2)?.filter_map(move |pair| {
+        let (var_t1, var_t2) = match pair {
+            Ok(pair) => pair,
+            Err(e) => return Some(Err(e)),
+        };
+        if var_t1.alleles.len() != 2 {
+            return None;
+        }
+        let delta = alt_freq(&var_t2, &samples_t2) - alt_freq(&var_t1, &samples_t1);
+        Some(Ok((var_t1.chrom, var_t1.pos, delta)))
+    }))
+}
+
+/// Compares the genotypes of the samples and sites shared by two VCF files.
+///
+/// Sites are matched by chrom, pos, REF and ALTs and samples by name. Both
+/// files must be coordinate-sorted with the same chromosome order. Genotypes
+/// are compared as allele sequences, so phase and ALT order are ignored.
+pub fn genotype_concordance(a: &PathBuf, b: &PathBuf) -> Result<ConcordanceReport, Box<dyn Error>> {
+    let shared_sites = SharedSites::from_files(a, b)?;
+    let (samples, sample_pairs) = shared_samples(a, b)?;
+
+    let mut per_sample = vec![ConcordanceCounts::default(); samples.len()];
+    let mut n_shared_sites = 0;
+    for pair in shared_sites {
+        let (var_a, var_b) = pair?;
+        n_shared_sites += 1;
+        for (counts, (idx_a, idx_b)) in per_sample.iter_mut().zip(&sample_pairs) {
+            counts.n_sites += 1;
+            let gt_a = genotype_alleles(&var_a, *idx_a);
+            let gt_b = genotype_alleles(&var_b, *idx_b);
+            let (gt_a, gt_b) = match (gt_a, gt_b) {
+                (Some(gt_a), Some(gt_b)) => (gt_a, gt_b),
+                (gt_a, gt_b) => {
+                    counts.n_missing_a += gt_a.is_none() as u64;
+                    counts.n_missing_b += gt_b.is_none() as u64;
+                    continue;
+                }
+            };
+            let matching = gt_a == gt_b;
+            counts.n_compared += 1;
+            counts.n_matching += matching as u64;
+            let ref_allele = var_a.alleles[0].as_str();
+            if gt_a.iter().chain(&gt_b).any(|allele| *allele != ref_allele) {
+                counts.n_non_ref_compared += 1;
+                counts.n_non_ref_matching += matching as u64;
+            }
+        }
+    }
+
+    let mut overall = ConcordanceCounts::default();
+    for counts in &per_sample {
+        overall.add(counts);
+    }
+    Ok(ConcordanceReport {
+        samples,
+        per_sample,
+        overall,
+        n_shared_sites,
+    })
+}
+
+fn sample_idx(fpath: &PathBuf, sample: &str) -> Result<usize, Box<dyn Error>> {
+    read_vcf_header(fpath)?
+        .samples
+        .iter()
+        .position(|name| name == sample)
+        .ok_or_else(|| format!("Sample `{sample}` not found in {}", fpath.display()).into())
+}
+
+/// The two alleles of a phased, called, diploid heterozygous genotype.
+fn phased_het_alleles(var: &Variant, sample_idx: usize) -> Option<(&str, &str)> {
+    match var.gts[sample_idx][..] {
+        [allele1, allele2]
+            if var.is_phased(sample_idx)
+                && allele1 != allele2
+                && allele1 != MISSING_ALLELE
+                && allele2 != MISSING_ALLELE =>
+        {
+            Some((
+                var.alleles[allele1 as usize].as_str(),
+                var.alleles[allele2 as usize].as_str(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Fraction of consecutive heterozygous sites at which the test phase switches
+/// with respect to the truth phase, for one sample.
+///
+/// Only the sites shared by both files in which the sample is a phased
+/// heterozygote with the same alleles in both are compared, and pairs of
+/// sites in different chromosomes are not. NaN if there are no such pairs.
+pub fn switch_error_rate(
+    truth: &PathBuf,
+    test: &PathBuf,
+    sample: &str,
+) -> Result<f64, Box<dyn Error>> {
+    let idx_truth = sample_idx(truth, sample)?;
+    let idx_test = sample_idx(test, sample)?;
+
+    let mut n_switches: u64 = 0;
+    let mut n_pairs: u64 = 0;
+    let mut prev_site: Option<(String, bool)> = None;
+    for pair in SharedSites::from_files(truth, test)? {
+        let (var_truth, var_test) = pair?;
+        let (Some(gt_truth), Some(gt_test)) = (
+            phased_het_alleles(&var_truth, idx_truth),
+            phased_het_alleles(&var_test, idx_test),
+        ) else {
+            continue;
+        };
+        let flipped = if gt_test == gt_truth {
+            false
+        } else if gt_test == (gt_truth.1, gt_truth.0) {
+            true
+        } else {
+            continue;
+        };
+        if let Some((prev_chrom, prev_flipped)) = &prev_site {
+            if *prev_chrom == var_truth.chrom {
+                n_pairs += 1;
+                n_switches += (flipped != *prev_flipped) as u64;
+            }
+        }
+        prev_site = Some((var_truth.chrom, flipped));
+    }
+    Ok(n_switches as f64 / n_pairs as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::write_temp_vcf;
+
+    const VCF_TRUTH: &str = "##fileformat=VCFv4.5
+##contig=<ID=1>
+##contig=<ID=2>
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT\t0/1\t0/0
+1\t20\t.\tC\tT,G\t.\tPASS\t.\tGT\t1/2\t1/1
+1\t30\t.\tC\tT\t.\tPASS\t.\tGT\t0/1\t0/0
+2\t5\t.\tA\tT\t.\tPASS\t.\tGT\t1/1\t0/1
+";
+
+    const VCF_TEST: &str = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS3\tS2\tS1
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT\t0/0\t0/0\t1|0
+1\t20\t.\tC\tG,T\t.\tPASS\t.\tGT\t0/0\t1/2\t1/2
+1\t30\t.\tC\tA\t.\tPASS\t.\tGT\t0/0\t0/0\t0/1
+1\t40\t.\tC\tA\t.\tPASS\t.\tGT\t0/0\t0/0\t0/1
+2\t5\t.\tA\tT\t.\tPASS\t.\tGT\t0/0\t./.\t0/1
+";
+
+    #[test]
+    fn concordance() {
+        let truth = write_temp_vcf("concordance_truth", VCF_TRUTH);
+        let test = write_temp_vcf("concordance_test", VCF_TEST);
+        let report = genotype_concordance(&truth, &test).unwrap();
+        assert_eq!(report.samples, vec!["S1", "S2"]);
+        assert_eq!(report.n_shared_sites, 3);
+
+        let s1 = &report.per_sample[0];
+        assert_eq!((s1.n_compared, s1.n_matching), (3, 2));
+        assert_eq!((s1.n_non_ref_compared, s1.n_non_ref_matching), (3, 2));
+        let s2 = &report.per_sample[1];
+        assert_eq!((s2.n_compared, s2.n_matching), (2, 1));
+        assert_eq!((s2.n_non_ref_compared, s2.n_non_ref_matching), (1, 0));
+        assert_eq!((s2.n_missing_a, s2.n_missing_b), (0, 1));
+        assert!((s2.missing_rate_b() - 1.0 / 3.0).abs() < 1e-12);
+
+        assert_eq!(report.overall.n_compared, 5);
+        assert!((report.overall.concordance() - 0.6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn freq_delta() {
+        let t1 = write_temp_vcf("freq_delta_t1", VCF_TRUTH);
+        let t2 = write_temp_vcf("freq_delta_t2", VCF_TEST);
+        let deltas: Vec<(String, u64, f64)> = allele_freq_delta(&t1, &t2)
+            .unwrap()
+            .map(|delta| delta.unwrap())
+            .collect();
+        // S3 is only in t2, 1:20 is multiallelic and 1:30 has other ALT.
+        assert_eq!(
+            deltas,
+            vec![("1".to_string(), 10, 0.0), ("2".to_string(), 5, 0.5 - 0.75),]
+        );
+    }
+
+    #[test]
+    fn intersection() {
+        let truth = write_temp_vcf("intersect_truth", VCF_TRUTH);
+        let test = write_temp_vcf("intersect_test", VCF_TEST);
+        let positions = |match_alts| {
+            let vars = intersect_vcfs(&test, &truth, match_alts, &ChromOrder::Header).unwrap();
+            assert_eq!(vars.samples, vec!["S3", "S2", "S1"]);
+            vars.vars_iter
+                .map(|var| var.unwrap().pos)
+                .collect::<Vec<u64>>()
+        };
+        assert_eq!(positions(true), vec![10, 20, 5]);
+        assert_eq!(positions(false), vec![10, 20, 30, 5]);
+    }
+
+    const VCF_PHASED_TRUTH: &str = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT\t0|1
+1\t20\t.\tA\tG\t.\tPASS\t.\tGT\t0|1
+1\t25\t.\tA\tG\t.\tPASS\t.\tGT\t1|1
+1\t30\t.\tA\tG\t.\tPASS\t.\tGT\t0|1
+1\t40\t.\tA\tG\t.\tPASS\t.\tGT\t0|1
+1\t50\t.\tA\tG\t.\tPASS\t.\tGT\t0|1
+2\t5\t.\tA\tG\t.\tPASS\t.\tGT\t0|1
+";
+
+    const VCF_PHASED_TEST: &str = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tX\tS1
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT\t0|0\t0|1
+1\t20\t.\tA\tG\t.\tPASS\t.\tGT\t0|0\t1|0
+1\t25\t.\tA\tG\t.\tPASS\t.\tGT\t0|0\t1|0
+1\t30\t.\tA\tG\t.\tPASS\t.\tGT\t0|0\t1|0
+1\t40\t.\tA\tG\t.\tPASS\t.\tGT\t0|0\t0|1
+1\t50\t.\tA\tG\t.\tPASS\t.\tGT\t0|0\t1/0
+2\t5\t.\tA\tG\t.\tPASS\t.\tGT\t0|0\t1|0
+";
+
+    #[test]
+    fn switch_errors() {
+        let truth = write_temp_vcf("switch_truth", VCF_PHASED_TRUTH);
+        let test = write_temp_vcf("switch_test", VCF_PHASED_TEST);
+        let rate = switch_error_rate(&truth, &test, "S1").unwrap();
+        assert!((rate - 2.0 / 3.0).abs() < 1e-12);
+        assert!(switch_error_rate(&truth, &test, "X").is_err());
+    }
+}
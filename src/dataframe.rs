@@ -0,0 +1,76 @@
+use crate::stats::{genotype_is_called, observed_and_expected_het, population_allele_freqs};
+use crate::Variants;
+use polars::prelude::*;
+
+/// One row per variant with site statistics computed over the given samples.
+///
+/// Columns: `chrom`, `pos`, `alt_freq` (frequency of all the non-reference
+/// alleles), `expected_het`, `observed_het`, `call_rate` and `maf` (frequency
+/// of the second most common allele). Frequencies and heterozygosities are NaN
+/// for sites with no called sample.
+pub fn site_stats_dataframe(
+    vars: &mut Variants,
+    samples: &[usize],
+) -> Result<DataFrame, Box<dyn std::error::Error>> {
+    let mut chroms = Vec::new();
+    let mut positions = Vec::new();
+    let mut alt_freqs = Vec::new();
+    let mut expected_hets = Vec::new();
+    let mut observed_hets = Vec::new();
+    let mut call_rates = Vec::new();
+    let mut mafs = Vec::new();
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        let mut freqs = population_allele_freqs(&var, samples);
+        let (ho, he) = observed_and_expected_het(&var, samples);
+        let n_called = samples
+            .iter()
+            .filter(|sample_idx| genotype_is_called(&var.gts[**sample_idx]))
+            .count();
+
+        chroms.push(var.chrom.clone());
+        positions.push(var.pos);
+        alt_freqs.push(freqs.first().map_or(f64::NAN, |ref_freq| 1.0 - ref_freq));
+        expected_hets.push(he);
+        observed_hets.push(ho);
+        call_rates.push(n_called as f64 / samples.len() as f64);
+        freqs.sort_by(|a, b| b.total_cmp(a));
+        mafs.push(match freqs.len() {
+            0 => f64::NAN,
+            1 => 0.0,
+            _ => freqs[1],
+        });
+    }
+    let df = df!(
+        "chrom" => chroms,
+        "pos" => positions,
+        "alt_freq" => alt_freqs,
+        "expected_het" => expected_hets,
+        "observed_het" => observed_hets,
+        "call_rate" => call_rates,
+        "maf" => mafs,
+    )?;
+    Ok(df)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{parse_vcf_buffer_str, VCF_MISSING};
+
+    #[test]
+    fn site_stats() {
+        let mut vars = parse_vcf_buffer_str(VCF_MISSING);
+        let df = site_stats_dataframe(&mut vars, &[0, 1, 2]).unwrap();
+        assert_eq!(df.shape(), (2, 7));
+
+        let call_rate = df.column("call_rate").unwrap().f64().unwrap();
+        assert!((call_rate.get(0).unwrap() - 2.0 / 3.0).abs() < 1e-12);
+        let alt_freq = df.column("alt_freq").unwrap().f64().unwrap();
+        assert!((alt_freq.get(0).unwrap() - 0.75).abs() < 1e-12);
+        let maf = df.column("maf").unwrap().f64().unwrap();
+        assert!((maf.get(1).unwrap() - 0.2).abs() < 1e-12);
+        let observed_het = df.column("observed_het").unwrap().f64().unwrap();
+        assert!((observed_het.get(1).unwrap() - 0.5).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,560 @@
+use crate::genotypes::alt_dosage;
+use crate::stats::weir_cockerham_components;
+use crate::windows::fixed_windows;
+use crate::{VCFParseError, Variant, Variants, VcfHeader, MISSING_ALLELE};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+fn parse_error_to_io(error: VCFParseError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn is_biallelic_snp(var: &Variant) -> bool {
+    var.alleles.len() == 2
+        && var.alleles.iter().all(|allele| {
+            allele.len() == 1 && matches!(allele.as_bytes()[0], b'A' | b'C' | b'G' | b'T')
+        })
+}
+
+fn plink_variant_id(var: &Variant) -> String {
+    if var.id == "." {
+        format!("{}:{}", var.chrom, var.pos)
+    } else {
+        var.id.clone()
+    }
+}
+
+/// Writes the samples in the 6-column PLINK PED format.
+///
+/// Only diploid biallelic SNPs are written, alleles are encoded by their base
+/// and missing genotypes as `0 0`. PED is sample-major, so every genotype is
+/// buffered before writing. Returns the number of skipped sites.
+pub fn write_plink_ped<W: Write>(mut writer: W, vars: &mut Variants) -> io::Result<u64> {
+    let mut rows: Vec<String> = vars
+        .samples
+        .iter()
+        .map(|sample| format!("{sample}\t{sample}\t0\t0\t0\t-9"))
+        .collect();
+    let mut n_skipped = 0;
+    for var in vars.vars_iter.by_ref() {
+        let var = var.map_err(parse_error_to_io)?;
+        if !is_biallelic_snp(&var) {
+            n_skipped += 1;
+            continue;
+        }
+        if var.ploidy != 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "PED requires diploid genotypes, found ploidy {}",
+                    var.ploidy
+                ),
+            ));
+        }
+        for (row, gt) in rows.iter_mut().zip(&var.gts) {
+            if gt.contains(&MISSING_ALLELE) {
+                row.push_str("\t0 0");
+            } else {
+                row.push('\t');
+                row.push_str(&var.alleles[gt[0] as usize]);
+                row.push(' ');
+                row.push_str(&var.alleles[gt[1] as usize]);
+            }
+        }
+    }
+    for row in rows {
+        writeln!(writer, "{row}")?;
+    }
+    Ok(n_skipped)
+}
+
+/// Writes the PLINK MAP companion of `write_plink_ped`, skipping the same sites.
+///
+/// Variants without ID are named `chrom:pos`. Returns the number of skipped sites.
+pub fn write_plink_map<W: Write>(mut writer: W, vars: &mut Variants) -> io::Result<u64> {
+    let mut n_skipped = 0;
+    for var in vars.vars_iter.by_ref() {
+        let var = var.map_err(parse_error_to_io)?;
+        if !is_biallelic_snp(&var) {
+            n_skipped += 1;
+            continue;
+        }
+        writeln!(
+            writer,
+            "{}\t{}\t0\t{}",
+            var.chrom,
+            plink_variant_id(&var),
+            var.pos
+        )?;
+    }
+    Ok(n_skipped)
+}
+
+/// Writes the genotypes in STRUCTURE format, one row per sample and chromosome copy.
+///
+/// Each row starts with the sample name followed by the allele index of every
+/// site, with `-9` for missing alleles. The number of rows per sample is the
+/// ploidy of the file, genotypes with fewer copies, like the haploid calls of
+/// a `mixed_ploidy` read, are padded with `-9`. All the genotypes are buffered.
+pub fn write_structure<W: Write>(mut writer: W, vars: &mut Variants) -> io::Result<()> {
+    let mut rows: Vec<Vec<String>> = vars
+        .samples
+        .iter()
+        .map(|sample| vec![sample.clone(); vars.ploidy as usize])
+        .collect();
+    for var in vars.vars_iter.by_ref() {
+        let var = var.map_err(parse_error_to_io)?;
+        for (sample_rows, gt) in rows.iter_mut().zip(&var.gts) {
+            if gt.len() > sample_rows.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "STRUCTURE rows are set by ploidy {}, found a genotype of ploidy {}",
+                        sample_rows.len(),
+                        gt.len()
+                    ),
+                ));
+            }
+            for (copy_idx, row) in sample_rows.iter_mut().enumerate() {
+                row.push('\t');
+                match gt.get(copy_idx) {
+                    Some(allele) if *allele != MISSING_ALLELE => row.push_str(&allele.to_string()),
+                    _ => row.push_str("-9"),
+                }
+            }
+        }
+    }
+    for row in rows.iter().flatten() {
+        writeln!(writer, "{row}")?;
+    }
+    Ok(())
+}
+
+/// Writes the genotypes in the EIGENSTRAT `.geno` format read by ADMIXTURE.
+///
+/// One line per site with one digit per sample: the number of reference
+/// alleles (0, 1 or 2), or `9` if missing. Only diploid biallelic sites are
+/// written. Returns the number of skipped sites.
+pub fn write_admixture_geno<W: Write>(mut writer: W, vars: &mut Variants) -> io::Result<u64> {
+    let mut n_skipped = 0;
+    let mut line = String::new();
+    for var in vars.vars_iter.by_ref() {
+        let var = var.map_err(parse_error_to_io)?;
+        if var.alleles.len() != 2 || var.ploidy != 2 {
+            n_skipped += 1;
+            continue;
+        }
+        line.clear();
+        for gt in &var.gts {
+            if gt.contains(&MISSING_ALLELE) {
+                line.push('9');
+            } else {
+                let n_ref = gt.iter().filter(|allele| **allele == 0).count();
+                line.push(char::from(b'0' + n_ref as u8));
+            }
+        }
+        writeln!(writer, "{line}")?;
+    }
+    Ok(n_skipped)
+}
+
+/// Writes the Weir & Cockerham Fst of every window of `window` bp as bedGraph
+/// lines: `chrom`, `start`, `end` and `fst`, tab-separated.
+///
+/// The input must be sorted. Windows without sites in which Fst can be
+/// computed are not written.
+pub fn write_windowed_fst<W: Write>(
+    mut writer: W,
+    vars: Variants,
+    pops: &[Vec<usize>],
+    window: u64,
+) -> io::Result<()> {
+    for window_vars in fixed_windows(vars, window) {
+        let (window, window_vars) = window_vars.map_err(parse_error_to_io)?;
+        let mut numerator = 0.0;
+        let mut denominator = 0.0;
+        for var in &window_vars {
+            if let Some((num, den)) = weir_cockerham_components(var, pops) {
+                numerator += num;
+                denominator += den;
+            }
+        }
+        let fst = numerator / denominator;
+        if fst.is_nan() {
+            continue;
+        }
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            window.chrom, window.start, window.end, fst
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the frequency of every ALT as it reads the variants, one line per
+/// ALT with the columns chrom, pos, ref, alt, af, ac and an.
+///
+/// AN counts the called alleles, and AF is NaN if there are none. Sites
+/// without ALT are not written.
+pub fn write_allele_freqs<W: Write>(mut writer: W, vars: Variants) -> io::Result<()> {
+    for var in vars.vars_iter {
+        let var = var.map_err(parse_error_to_io)?;
+        let counts = var.allele_counts();
+        let n_alleles: u64 = counts.iter().sum();
+        for (alt_idx, alt_allele) in var.alt_alleles().iter().enumerate() {
+            let count = counts.get(alt_idx + 1).copied().unwrap_or(0);
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                var.chrom,
+                var.pos,
+                var.ref_allele(),
+                alt_allele,
+                count as f64 / n_alleles as f64,
+                count,
+                n_alleles
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the reference span of every variant as a BED line: chrom, 0-based
+/// start, exclusive end and ID.
+///
+/// The span goes from POS to `Variant::span_end`, so it covers the whole REF,
+/// or up to the INFO `END` of structural variants, and never ends before it
+/// starts.
+pub fn write_bed<W: Write>(mut writer: W, vars: Variants) -> io::Result<()> {
+    for var in vars.vars_iter {
+        let var = var.map_err(parse_error_to_io)?;
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}",
+            var.chrom,
+            var.pos.saturating_sub(1),
+            var.span_end(),
+            var.id
+        )?;
+    }
+    Ok(())
+}
+
+/// The FORMAT of a variant with the subfields that it keeps: GT, PS if the
+/// line had it and those kept with `VcfReaderBuilder::format_fields`.
+fn kept_format(var: &Variant) -> String {
+    let mut keys = vec!["GT"];
+    if !var.phase_sets.is_empty() {
+        keys.push("PS");
+    }
+    keys.extend(
+        var.format_fields
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .filter(|key| *key != "PS"),
+    );
+    keys.join(":")
+}
+
+/// Writes every variant to a VCF file as it is read, while still yielding it.
+///
+/// The new file gets the meta lines of `header`, usually that of the source
+/// read with `read_vcf_header`, and the FORMAT of every line the subfields the
+/// variant kept, see `Variant::to_vcf_line`. Write errors are yielded as
+/// `WriteError`, after the last variant for those of the final flush.
+pub fn tee_to_file<'a>(
+    vars: Variants<'a>,
+    header: &VcfHeader,
+    out: &PathBuf,
+) -> io::Result<Variants<'a>> {
+    let mut writer = BufWriter::new(File::create(out)?);
+    for line in &header.lines {
+        writeln!(writer, "{line}")?;
+    }
+    write!(writer, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")?;
+    if !vars.samples.is_empty() {
+        write!(writer, "\tFORMAT\t{}", vars.samples.join("\t"))?;
+    }
+    writeln!(writer)?;
+
+    let write_error = |e: io::Error| VCFParseError::WriteError(e.to_string());
+    let mut vars_iter = vars.vars_iter;
+    let mut writer = Some(writer);
+    let teed_iter = std::iter::from_fn(move || {
+        let out = writer.as_mut()?;
+        match vars_iter.next() {
+            Some(Ok(var)) => {
+                let mut line = var.to_vcf_line(&kept_format(&var));
+                if var.gts.is_empty() {
+                    line.truncate(line.rfind('\t').unwrap_or(line.len()));
+                }
+                match writeln!(out, "{line}") {
+                    Ok(()) => Some(Ok(var)),
+                    Err(e) => Some(Err(write_error(e))),
+                }
+            }
+            Some(Err(e)) => Some(Err(e)),
+            None => writer.take()?.flush().err().map(|e| Err(write_error(e))),
+        }
+    });
+    Ok(Variants::new(
+        vars.samples,
+        teed_iter,
+        vars.ploidy,
+        vars.ploidy_confident,
+    ))
+}
+
+/// Bytes reserved for the `.npy` header, enough for any shape of two `u64`.
+const NPY_HEADER_LEN: usize = 128;
+
+/// The `.npy` version 1.0 header of an int8 C-ordered matrix, padded with
+/// spaces to `NPY_HEADER_LEN` bytes.
+fn npy_header(n_rows: u64, n_cols: u64) -> Vec<u8> {
+    let dict =
+        format!("{{'descr': '|i1', 'fortran_order': False, 'shape': ({n_rows}, {n_cols}), }}");
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend(((NPY_HEADER_LEN - 10) as u16).to_le_bytes());
+    header.extend(dict.as_bytes());
+    header.resize(NPY_HEADER_LEN - 1, b' ');
+    header.push(b'\n');
+    header
+}
+
+/// Writes the `(n_variants, n_samples)` matrix of ALT dosages of the biallelic
+/// sites as an int8 `.npy` file, loadable with `numpy.load`, with -1 for
+/// missing genotypes.
+///
+/// The samples are written, one per line, to a `.samples.txt` file next to it,
+/// and the chrom and pos of the rows to a `.positions.tsv` file. The matrix is
+/// streamed, and its shape is written at the end.
+pub fn write_npy(path: &PathBuf, vars: &mut Variants) -> io::Result<()> {
+    let mut samples = BufWriter::new(File::create(path.with_extension("samples.txt"))?);
+    for sample in &vars.samples {
+        writeln!(samples, "{sample}")?;
+    }
+    samples.flush()?;
+
+    let mut positions = BufWriter::new(File::create(path.with_extension("positions.tsv"))?);
+    let mut npy = BufWriter::new(File::create(path)?);
+    npy.write_all(&npy_header(0, 0))?;
+    let mut n_vars = 0;
+    let mut row = Vec::with_capacity(vars.samples.len());
+    for var in vars.vars_iter.by_ref() {
+        let var = var.map_err(parse_error_to_io)?;
+        if var.alleles.len() != 2 {
+            continue;
+        }
+        row.clear();
+        row.extend(var.gts.iter().map(|gt| alt_dosage(gt) as i8 as u8));
+        npy.write_all(&row)?;
+        writeln!(positions, "{}\t{}", var.chrom, var.pos)?;
+        n_vars += 1;
+    }
+    positions.flush()?;
+    npy.seek(SeekFrom::Start(0))?;
+    npy.write_all(&npy_header(n_vars, vars.samples.len() as u64))?;
+    npy.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::read_header;
+    use crate::tests::{parse_vcf_buffer_str, VCF_45, VCF_MISSING};
+    use crate::VcfReaderBuilder;
+
+    #[test]
+    fn plink_ped() {
+        let mut vars = parse_vcf_buffer_str(VCF_45);
+        let mut out = Vec::new();
+        let n_skipped = write_plink_ped(&mut out, &mut vars).unwrap();
+        assert_eq!(n_skipped, 4);
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "NA00001\tNA00001\t0\t0\t0\t-9\tG G\tT T");
+        assert_eq!(lines[1], "NA00002\tNA00002\t0\t0\t0\t-9\tA G\tT A");
+        assert_eq!(lines[2], "NA00003\tNA00003\t0\t0\t0\t-9\tA A\tT T");
+
+        let mut vars = parse_vcf_buffer_str(VCF_MISSING);
+        let mut out = Vec::new();
+        write_plink_ped(&mut out, &mut vars).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().nth(1).unwrap(), "S2\tS2\t0\t0\t0\t-9\t0 0\tC C");
+    }
+
+    #[test]
+    fn plink_map() {
+        let mut vars = parse_vcf_buffer_str(VCF_45);
+        let mut out = Vec::new();
+        let n_skipped = write_plink_map(&mut out, &mut vars).unwrap();
+        assert_eq!(n_skipped, 4);
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out, "20\trs6054257\t0\t14370\n20\t20:17330\t0\t17330\n");
+    }
+
+    #[test]
+    fn structure() {
+        let mut vars = parse_vcf_buffer_str(VCF_MISSING);
+        let mut out = Vec::new();
+        write_structure(&mut out, &mut vars).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let expected = "S1\t0\t0
+S1\t1\t-9
+S2\t-9\t0
+S2\t-9\t0
+S3\t1\t1
+S3\t1\t0
+";
+        assert_eq!(out, expected);
+
+        let vcf = VCF_MISSING.replace("\t1/1\n", "\t1\n");
+        let mut vars = VcfReaderBuilder::new()
+            .mixed_ploidy(true)
+            .read_buffer(vcf.as_bytes())
+            .unwrap();
+        let mut out = Vec::new();
+        write_structure(&mut out, &mut vars).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.ends_with("S3\t1\t1\nS3\t-9\t0\n"));
+
+        let vcf = VCF_MISSING.replace("\t1|0", "\t1|0|0");
+        let mut vars = VcfReaderBuilder::new()
+            .mixed_ploidy(true)
+            .read_buffer(vcf.as_bytes())
+            .unwrap();
+        assert!(write_structure(&mut Vec::new(), &mut vars).is_err());
+    }
+
+    #[test]
+    fn admixture_geno() {
+        let mut vars = parse_vcf_buffer_str(VCF_45);
+        let mut out = Vec::new();
+        let n_skipped = write_admixture_geno(&mut out, &mut vars).unwrap();
+        assert_eq!(n_skipped, 4);
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out, "210\n212\n");
+
+        let mut vars = parse_vcf_buffer_str(VCF_MISSING);
+        let mut out = Vec::new();
+        write_admixture_geno(&mut out, &mut vars).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "190\n921\n");
+    }
+
+    #[test]
+    fn windowed_fst() {
+        let vcf = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA1\tA2\tB1\tB2
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT\t0/0\t0/0\t1/1\t1/1
+1\t150\t.\tA\tG\t.\tPASS\t.\tGT\t0/1\t0/1\t0/1\t0/1
+1\t250\t.\tA\tG\t.\tPASS\t.\tGT\t0/1\t./.\t0/1\t0/1";
+        let vars = parse_vcf_buffer_str(vcf);
+        let mut out = Vec::new();
+        write_windowed_fst(&mut out, vars, &[vec![0, 1], vec![2, 3]], 100).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out, "1\t0\t100\t1\n1\t100\t200\t0\n");
+    }
+
+    #[test]
+    fn allele_freqs() {
+        let mut out = Vec::new();
+        write_allele_freqs(&mut out, parse_vcf_buffer_str(VCF_45)).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "20\t14370\tG\tA\t0.5\t3\t6");
+        assert_eq!(lines[2], "20\t1110696\tA\tG\t0.3333333333333333\t2\t6");
+        assert_eq!(lines[3], "20\t1110696\tA\tT\t0.6666666666666666\t4\t6");
+        assert_eq!(lines[4], "20\t1234567\tGTC\tG\t0.5\t3\t6");
+    }
+
+    #[test]
+    fn bed() {
+        let mut out = Vec::new();
+        write_bed(&mut out, parse_vcf_buffer_str(VCF_45)).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "20\t14369\t14370\trs6054257");
+        assert_eq!(lines[1], "20\t17329\t17330\t.");
+        assert_eq!(lines[4], "20\t1234566\t1234569\tmicrosat1");
+
+        let telomeric = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+20\t0\t.\tN\tG\t.\tPASS\t.";
+        let mut out = Vec::new();
+        write_bed(&mut out, parse_vcf_buffer_str(telomeric)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "20\t0\t0\t.\n");
+
+        let end_before_pos = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+20\t100\tdel1\tA\t<DEL>\t.\tPASS\tEND=50";
+        let mut out = Vec::new();
+        write_bed(&mut out, parse_vcf_buffer_str(end_before_pos)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "20\t99\t100\tdel1\n");
+    }
+
+    #[test]
+    fn tee() {
+        let path = std::env::temp_dir().join("nei_rs_teed.vcf");
+        let header = read_header(&mut VCF_45.as_bytes()).unwrap();
+        let vars = parse_vcf_buffer_str(VCF_45).filter_mac(2);
+        let teed = tee_to_file(vars, &header, &path).unwrap();
+        let positions: Vec<u64> = teed.vars_iter.map(|var| var.unwrap().pos).collect();
+        assert_eq!(positions, vec![14370, 1110696, 1234567, 1234567]);
+
+        let expected: Vec<Variant> = parse_vcf_buffer_str(VCF_45)
+            .filter_mac(2)
+            .vars_iter
+            .map(|var| var.unwrap())
+            .collect();
+        assert_eq!(crate::read_vcf_header(&path).unwrap().lines, header.lines);
+        let reread = crate::read_vcf_file(&path).unwrap();
+        assert_eq!(reread.samples, vec!["NA00001", "NA00002", "NA00003"]);
+        let reread: Vec<Variant> = reread.vars_iter.map(|var| var.unwrap()).collect();
+        assert_eq!(reread.len(), expected.len());
+        for (reread, expected) in reread.iter().zip(&expected) {
+            assert!(reread.same_record(expected));
+            assert_eq!(reread.info, expected.info);
+            assert_eq!(reread.filter_status(), expected.filter_status());
+            assert_eq!(reread.phased, expected.phased);
+        }
+
+        let sites_only = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+20\t100\trs1\tA\tG\t.\tPASS\tAF=0.1";
+        let header = read_header(&mut sites_only.as_bytes()).unwrap();
+        let teed = tee_to_file(parse_vcf_buffer_str(sites_only), &header, &path).unwrap();
+        assert_eq!(teed.vars_iter.count(), 1);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{sites_only}\n")
+        );
+    }
+
+    #[test]
+    fn npy() {
+        let path = std::env::temp_dir().join("nei_rs_dosages.npy");
+        write_npy(&path, &mut parse_vcf_buffer_str(VCF_MISSING)).unwrap();
+        let npy = std::fs::read(&path).unwrap();
+        assert_eq!(&npy[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        // numpy requires the data to be aligned to 64 bytes.
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&npy[10..10 + header_len]).unwrap();
+        assert!(header.starts_with("{'descr': '|i1', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with('\n'));
+        let dosages: Vec<i8> = npy[10 + header_len..]
+            .iter()
+            .map(|byte| *byte as i8)
+            .collect();
+        assert_eq!(dosages, vec![1, -1, 2, -1, 0, 1]);
+
+        let samples = std::fs::read_to_string(path.with_extension("samples.txt")).unwrap();
+        assert_eq!(samples, "S1\nS2\nS3\n");
+        let positions = std::fs::read_to_string(path.with_extension("positions.tsv")).unwrap();
+        assert_eq!(positions, "20\t100\n20\t200\n");
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{AlleleInt, VCFParseError, Variant, Variants, MISSING_ALLELE};
+use ndarray::Array2;
+
+/// Number of non-reference alleles of a genotype, -1 if any allele is missing.
+pub(crate) fn alt_dosage(gt: &[AlleleInt]) -> AlleleInt {
+    if gt.contains(&MISSING_ALLELE) {
+        return MISSING_ALLELE;
+    }
+    gt.iter().filter(|allele| **allele != 0).count() as AlleleInt
+}
+
+fn is_biallelic(var: &Variant) -> bool {
+    var.alleles.len() == 2
+}
+
+/// Sample-major `(n_samples, n_variants)` matrix of alternative allele dosages.
+///
+/// Only biallelic sites are included and missing genotypes are -1. All the
+/// dosages are buffered: the matrix takes `size_of::<AlleleInt>()` bytes per
+/// sample and site, 1 or 2 depending on the `i8` feature, and twice that while
+/// it is being transposed.
+pub fn transpose_genotypes(vars: &mut Variants) -> Result<Array2<AlleleInt>, VCFParseError> {
+    let n_samples = vars.samples.len();
+    let mut dosages = Vec::new();
+    let mut n_vars = 0;
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        if !is_biallelic(&var) {
+            continue;
+        }
+        dosages.extend(var.gts.iter().map(|gt| alt_dosage(gt)));
+        n_vars += 1;
+    }
+    let dosages = Array2::from_shape_vec((n_vars, n_samples), dosages)
+        .expect("Every variant has a genotype per sample");
+    Ok(dosages.reversed_axes().as_standard_layout().into_owned())
+}
+
+/// How the copies of an allele in a genotype are encoded, as in PLINK.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneticModel {
+    /// The number of copies.
+    Additive,
+    /// 1 if there is any copy, 0 otherwise.
+    Dominant,
+    /// 1 if every allele is a copy, 0 otherwise.
+    Recessive,
+}
+
+impl GeneticModel {
+    fn encode(&self, gt: &[AlleleInt], allele: AlleleInt) -> f32 {
+        if gt.contains(&MISSING_ALLELE) {
+            return f32::NAN;
+        }
+        let n_copies = gt.iter().filter(|gt_allele| **gt_allele == allele).count();
+        match self {
+            GeneticModel::Additive => n_copies as f32,
+            GeneticModel::Dominant => (n_copies > 0) as u8 as f32,
+            GeneticModel::Recessive => (n_copies == gt.len()) as u8 as f32,
+        }
+    }
+}
+
+/// Sample-major `(n_samples, n_variants)` matrix with the genotypes encoded
+/// by the copies of the allele `alt_index`, 1 being the first ALT.
+///
+/// Only the sites that have that allele are included, and missing genotypes
+/// are NaN.
+pub fn encode_genotypes(
+    vars: &mut Variants,
+    model: GeneticModel,
+    alt_index: usize,
+) -> Result<Array2<f32>, VCFParseError> {
+    let n_samples = vars.samples.len();
+    let mut encoded = Vec::new();
+    let mut n_vars = 0;
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        if alt_index == 0 || alt_index >= var.alleles.len() {
+            continue;
+        }
+        encoded.extend(
+            var.gts
+                .iter()
+                .map(|gt| model.encode(gt, alt_index as AlleleInt)),
+        );
+        n_vars += 1;
+    }
+    let encoded = Array2::from_shape_vec((n_vars, n_samples), encoded)
+        .expect("Every variant has a genotype per sample");
+    Ok(encoded.reversed_axes().as_standard_layout().into_owned())
+}
+
+/// 64-bit FNV-1a, a hash that does not change between Rust versions or platforms.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(Fnv1a::PRIME);
+        }
+    }
+
+    /// Writes a string followed by a byte that can not be found in UTF-8.
+    fn write_str(&mut self, string: &str) {
+        self.write(string.as_bytes());
+        self.write(&[0xff]);
+    }
+}
+
+/// Order-sensitive hash of the chrom, pos, alleles and genotypes of every variant.
+///
+/// Alleles are hashed sorted, with the genotypes recoded accordingly, so the
+/// order of the ALTs does not matter, and neither do ID, QUAL, FILTER, INFO,
+/// the FORMAT fields other than GT, the `/` or `|` separators or the
+/// whitespace of the file. The order of the alleles within each genotype does
+/// matter. Two files with the same genotypes in the same order have the same
+/// fingerprint, whether or not the crate is built with the `i8` feature.
+pub fn genotype_fingerprint(vars: &mut Variants) -> Result<u64, VCFParseError> {
+    let mut hasher = Fnv1a(Fnv1a::OFFSET_BASIS);
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        hasher.write_str(&var.chrom);
+        hasher.write(&var.pos.to_le_bytes());
+
+        let mut sorted_idxs: Vec<usize> = (0..var.alleles.len()).collect();
+        sorted_idxs.sort_by_key(|idx| &var.alleles[*idx]);
+        let mut new_idxs = vec![0; var.alleles.len()];
+        for (new_idx, old_idx) in sorted_idxs.iter().enumerate() {
+            hasher.write_str(&var.alleles[*old_idx]);
+            new_idxs[*old_idx] = new_idx as AlleleInt;
+        }
+        for gt in &var.gts {
+            hasher.write(&[gt.len() as u8]);
+            for allele in gt {
+                let allele = match new_idxs.get(*allele as usize) {
+                    Some(new_idx) if *allele != MISSING_ALLELE => *new_idx,
+                    _ => *allele,
+                };
+                hasher.write(&(allele as i16).to_le_bytes());
+            }
+        }
+    }
+    Ok(hasher.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{parse_vcf_buffer_str, VCF_45, VCF_MISSING};
+    use ndarray::array;
+
+    #[test]
+    fn sample_major_dosages() {
+        let mut vars = parse_vcf_buffer_str(VCF_MISSING);
+        let dosages = transpose_genotypes(&mut vars).unwrap();
+        assert_eq!(dosages, array![[1, -1], [-1, 0], [2, 1]]);
+        assert!(dosages.is_standard_layout());
+    }
+
+    #[test]
+    fn genetic_models() {
+        let encode = |model, alt_index| {
+            encode_genotypes(&mut parse_vcf_buffer_str(VCF_45), model, alt_index).unwrap()
+        };
+        let additive = encode(GeneticModel::Additive, 1);
+        assert_eq!(additive.dim(), (3, 5));
+        assert_eq!(additive.row(2).to_vec(), vec![2.0, 0.0, 0.0, 2.0, 2.0]);
+        assert_eq!(
+            encode(GeneticModel::Dominant, 1).row(1).to_vec(),
+            vec![1.0, 1.0, 1.0, 0.0, 0.0]
+        );
+        assert_eq!(
+            encode(GeneticModel::Recessive, 1).row(2).to_vec(),
+            vec![1.0, 0.0, 0.0, 1.0, 1.0]
+        );
+        // Only the multiallelic sites have a second ALT.
+        assert_eq!(
+            encode(GeneticModel::Additive, 2).row(1).to_vec(),
+            vec![1.0, 1.0, 1.0]
+        );
+
+        let missing = encode_genotypes(
+            &mut parse_vcf_buffer_str(VCF_MISSING),
+            GeneticModel::Dominant,
+            1,
+        )
+        .unwrap();
+        assert!(missing[[1, 0]].is_nan() && missing[[0, 1]].is_nan());
+        assert_eq!(missing[[1, 1]], 0.0);
+    }
+
+    fn fingerprint(vcf: &str) -> u64 {
+        genotype_fingerprint(&mut parse_vcf_buffer_str(vcf)).unwrap()
+    }
+
+    #[test]
+    fn fingerprints() {
+        let reference = fingerprint(VCF_45);
+        // Pinned, it must not change with the `i8` feature.
+        assert_eq!(reference, 6640907325772333849);
+        assert_eq!(fingerprint(&VCF_45.replace("AA=T;DB", "DB")), reference);
+        let swapped_alts = VCF_45.replace(
+            "A\tG,T\t67\tPASS\tNS=2;DP=10;AF=0.333,0.667;AA=T;DB\tGT:GQ:DP:HQ\t1|2:21:6:23,27\t2|1:2:0:18,2\t2/2",
+            "A\tT,G\t67\tPASS\tNS=2;DP=10;AF=0.333,0.667;AA=T;DB\tGT:GQ:DP:HQ\t2|1:21:6:23,27\t1|2:2:0:18,2\t1/1",
+        );
+        assert_ne!(swapped_alts, VCF_45);
+        assert_eq!(fingerprint(&swapped_alts), reference);
+
+        assert_ne!(
+            fingerprint(&VCF_MISSING.replace("1|0", "0|0")),
+            fingerprint(VCF_MISSING)
+        );
+        let lines: Vec<&str> = VCF_MISSING.lines().collect();
+        let reordered = [lines[0], lines[1], lines[3], lines[2]].join("\n");
+        assert_ne!(fingerprint(&reordered), fingerprint(VCF_MISSING));
+    }
+}
@@ -0,0 +1,131 @@
+//! HDF5 dump of the genotypes following the scikit-allel layout:
+//!
+//! - `samples`: sample names.
+//! - `variants/CHROM`, `variants/POS`: site coordinates.
+//! - `calldata/GT`: int8 `(n_variants, n_samples, ploidy)` allele indexes, -1 if missing.
+
+use crate::{VCFParseError, Variants};
+use hdf5_metno::types::VarLenUnicode;
+use hdf5_metno::File;
+use ndarray::Array3;
+use std::error::Error;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct Hdf5Options {
+    /// Number of variants per chunk of the datasets.
+    pub chunk_variants: usize,
+    /// Deflate (gzip) level, from 0 to 9.
+    pub compression_level: u8,
+}
+
+impl Default for Hdf5Options {
+    fn default() -> Hdf5Options {
+        Hdf5Options {
+            chunk_variants: 10_000,
+            compression_level: 1,
+        }
+    }
+}
+
+fn to_var_len_unicode(strings: &[String]) -> Result<Vec<VarLenUnicode>, Box<dyn Error>> {
+    let mut var_len_strings = Vec::with_capacity(strings.len());
+    for string in strings {
+        var_len_strings.push(string.parse::<VarLenUnicode>()?);
+    }
+    Ok(var_len_strings)
+}
+
+pub fn write_hdf5(path: &PathBuf, vars: &mut Variants) -> Result<(), Box<dyn Error>> {
+    write_hdf5_with_options(path, vars, &Hdf5Options::default())
+}
+
+/// Writes the variants to a new HDF5 file, buffering all the genotypes.
+pub fn write_hdf5_with_options(
+    path: &PathBuf,
+    vars: &mut Variants,
+    options: &Hdf5Options,
+) -> Result<(), Box<dyn Error>> {
+    let n_samples = vars.samples.len();
+    let ploidy = vars.ploidy as usize;
+    let mut chroms = Vec::new();
+    let mut positions = Vec::new();
+    let mut gts = Vec::new();
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        for allele in var.gts.iter().flatten() {
+            match i8::try_from(*allele) {
+                Ok(allele) => gts.push(allele),
+                Err(_) => {
+                    return Err(Box::new(VCFParseError::IncorrectAllele(
+                        allele.to_string(),
+                        format!("{}:{}", var.chrom, var.pos),
+                    )))
+                }
+            }
+        }
+        chroms.push(var.chrom);
+        positions.push(var.pos);
+    }
+    let n_vars = positions.len();
+    let chunk_variants = options.chunk_variants.min(n_vars).max(1);
+
+    let file = File::create(path)?;
+    file.new_dataset_builder()
+        .with_data(&to_var_len_unicode(&vars.samples)?[..])
+        .create("samples")?;
+
+    let variants = file.create_group("variants")?;
+    variants
+        .new_dataset_builder()
+        .chunk(chunk_variants)
+        .deflate(options.compression_level)
+        .with_data(&to_var_len_unicode(&chroms)?[..])
+        .create("CHROM")?;
+    variants
+        .new_dataset_builder()
+        .chunk(chunk_variants)
+        .deflate(options.compression_level)
+        .with_data(&positions[..])
+        .create("POS")?;
+
+    let gts = Array3::from_shape_vec((n_vars, n_samples, ploidy), gts)?;
+    file.create_group("calldata")?
+        .new_dataset_builder()
+        .chunk((chunk_variants, n_samples.max(1), ploidy.max(1)))
+        .deflate(options.compression_level)
+        .with_data(&gts)
+        .create("GT")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{parse_vcf_buffer_str, VCF_MISSING};
+    use ndarray::Ix3;
+
+    #[test]
+    fn hdf5_round_trip() {
+        let path = std::env::temp_dir().join("nei_rs_hdf5_round_trip.h5");
+        let mut vars = parse_vcf_buffer_str(VCF_MISSING);
+        write_hdf5(&path, &mut vars).unwrap();
+
+        let file = File::open(&path).unwrap();
+        let positions = file
+            .dataset("variants/POS")
+            .unwrap()
+            .read_raw::<u64>()
+            .unwrap();
+        assert_eq!(positions, vec![100, 200]);
+        let gts = file
+            .dataset("calldata/GT")
+            .unwrap()
+            .read::<i8, Ix3>()
+            .unwrap();
+        assert_eq!(gts.shape(), &[2, 3, 2]);
+        assert_eq!(gts[[0, 1, 0]], -1);
+        assert_eq!(gts[[1, 2, 0]], 1);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{read_sample_line, VCFParseError};
+use std::collections::HashMap;
+use std::io::BufRead;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contig {
+    pub id: String,
+    pub length: Option<u64>,
+}
+
+/// An `##INFO` or `##FORMAT` definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDefinition {
+    pub id: String,
+    pub number: String,
+    pub kind: String,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VcfHeader {
+    pub samples: Vec<String>,
+    pub contigs: Vec<Contig>,
+    pub infos: Vec<FieldDefinition>,
+    pub formats: Vec<FieldDefinition>,
+    /// The `##` meta-information lines, without the line break.
+    pub lines: Vec<String>,
+}
+
+/// Parses the `key=value` pairs of a structured header value like
+/// `<ID=DP,Number=1,Description="Total, depth">`, unquoting the values.
+fn parse_structured_value(value: &str) -> HashMap<String, String> {
+    let value = value.trim_start_matches('<').trim_end_matches('>');
+    let mut items = HashMap::new();
+    let mut item = String::new();
+    let mut in_quotes = false;
+    for chr in value.chars().chain(std::iter::once(',')) {
+        match chr {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                if let Some((key, value)) = item.split_once('=') {
+                    items.insert(key.to_string(), value.to_string());
+                }
+                item.clear();
+            }
+            _ => item.push(chr),
+        }
+    }
+    items
+}
+
+fn parse_field_definition(value: &str) -> FieldDefinition {
+    let mut items = parse_structured_value(value);
+    let mut take = |key: &str| items.remove(key).unwrap_or_default();
+    FieldDefinition {
+        id: take("ID"),
+        number: take("Number"),
+        kind: take("Type"),
+        description: take("Description"),
+    }
+}
+
+impl VcfHeader {
+    fn add_meta_line(&mut self, line: &str) {
+        if let Some(value) = line.strip_prefix("##contig=") {
+            let items = parse_structured_value(value);
+            self.contigs.push(Contig {
+                id: items.get("ID").cloned().unwrap_or_default(),
+                length: items.get("length").and_then(|length| length.parse().ok()),
+            });
+        } else if let Some(value) = line.strip_prefix("##INFO=") {
+            self.infos.push(parse_field_definition(value));
+        } else if let Some(value) = line.strip_prefix("##FORMAT=") {
+            self.formats.push(parse_field_definition(value));
+        }
+        self.lines.push(line.to_string());
+    }
+}
+
+/// Reads the header up to, and including, the `#CHROM` line.
+pub(crate) fn read_header<T: BufRead>(file: &mut T) -> Result<VcfHeader, VCFParseError> {
+    let mut header = VcfHeader::default();
+    loop {
+        let mut line = String::new();
+        match file.read_line(&mut line) {
+            Ok(0) => return Err(VCFParseError::EmptyFile),
+            Ok(_) => (),
+            Err(_) => return Err(VCFParseError::ReadLineError(0)),
+        }
+        if line.starts_with("##") {
+            header.add_meta_line(line.trim_end());
+        } else if line.starts_with("#CHROM") {
+            header.samples = read_sample_line(&line)?;
+            return Ok(header);
+        } else {
+            return Err(VCFParseError::InvalidSampleLine(line));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::VCF_45;
+
+    #[test]
+    fn vcf_45_header() {
+        let header = read_header(&mut VCF_45.as_bytes()).unwrap();
+        assert_eq!(header.samples, vec!["NA00001", "NA00002", "NA00003"]);
+        assert_eq!(
+            header.contigs,
+            vec![Contig {
+                id: "20".to_string(),
+                length: Some(62435964)
+            }]
+        );
+        assert_eq!(header.infos.len(), 6);
+        assert_eq!(
+            header.infos[4],
+            FieldDefinition {
+                id: "DB".to_string(),
+                number: "0".to_string(),
+                kind: "Flag".to_string(),
+                description: "dbSNP membership, build 129".to_string(),
+            }
+        );
+        assert_eq!(header.formats.len(), 4);
+        assert_eq!(header.lines.len(), 18);
+        assert_eq!(header.lines[0], "##fileformat=VCFv4.5");
+    }
+}
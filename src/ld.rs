@@ -0,0 +1,155 @@
+//! Linkage disequilibrium between pairs of loci.
+//!
+//! Haplotypes are taken from the genotypes of the samples in which they are
+//! known: both loci phased, or at least one of them homozygous. Samples with
+//! missing alleles in any locus are ignored.
+
+use crate::stats::genotype_is_called;
+use crate::{AlleleInt, Variant};
+
+fn is_homozygous(gt: &[AlleleInt]) -> bool {
+    gt.iter().all(|allele| *allele == gt[0])
+}
+
+/// `(n_alleles_a, n_alleles_b)` matrix with the frequencies of the two-locus
+/// haplotypes of the samples, None if no sample has known haplotypes.
+fn haplotype_freqs(a: &Variant, b: &Variant, samples: &[usize]) -> Option<Vec<Vec<f64>>> {
+    let mut counts = vec![vec![0u64; b.alleles.len()]; a.alleles.len()];
+    let mut n_haplotypes = 0;
+    for sample_idx in samples {
+        let (gt_a, gt_b) = (&a.gts[*sample_idx], &b.gts[*sample_idx]);
+        let is_known = (a.is_phased(*sample_idx) && b.is_phased(*sample_idx))
+            || is_homozygous(gt_a)
+            || is_homozygous(gt_b);
+        if !genotype_is_called(gt_a) || !genotype_is_called(gt_b) || !is_known {
+            continue;
+        }
+        for (allele_a, allele_b) in gt_a.iter().zip(gt_b) {
+            // Alleles beyond ALT are ignored.
+            if let Some(count) = counts
+                .get_mut(*allele_a as usize)
+                .and_then(|row| row.get_mut(*allele_b as usize))
+            {
+                *count += 1;
+                n_haplotypes += 1;
+            }
+        }
+    }
+    if n_haplotypes == 0 {
+        return None;
+    }
+    Some(
+        counts
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|count| *count as f64 / n_haplotypes as f64)
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+/// Ohta's (1982) components of the variance of linkage disequilibrium in a
+/// subdivided population.
+///
+/// With `x_ijk` the frequency of the haplotype of alleles `j` and `k` in the
+/// subpopulation `i`, `p_ij` and `q_ik` the allele frequencies, and bars for
+/// the means over the subpopulations, every component is summed over all the
+/// allele pairs and averaged over the subpopulations:
+/// `D2_IS = E[(x_ijk - p_ij q_ik)^2]`, `D2_ST = E[(p_ij q_ik - p_j q_k)^2]`,
+/// `D'2_IS = E[(x_ijk - x_jk)^2]`, `D'2_ST = (x_jk - p_j q_k)^2` and
+/// `D2_IT = E[(x_ijk - p_j q_k)^2]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OhtaD {
+    pub d2_is: f64,
+    pub d2_st: f64,
+    pub d2_is_prime: f64,
+    pub d2_st_prime: f64,
+    pub d2_it: f64,
+}
+
+/// Ohta's D statistics of two loci, see `OhtaD`.
+///
+/// Subpopulations are weighted equally, and those without known haplotypes
+/// are left out. `D2_IT = D'2_IS + D'2_ST` always holds, while
+/// `D2_IT = D2_IS + D2_ST` only approximately. None if no subpopulation has
+/// known haplotypes.
+pub fn ohta_d_stats(a: &Variant, b: &Variant, pops: &[Vec<usize>]) -> Option<OhtaD> {
+    let freqs: Vec<Vec<Vec<f64>>> = pops
+        .iter()
+        .filter_map(|pop| haplotype_freqs(a, b, pop))
+        .collect();
+    if freqs.is_empty() {
+        return None;
+    }
+    let n_pops = freqs.len() as f64;
+    let (n_alleles_a, n_alleles_b) = (a.alleles.len(), b.alleles.len());
+    let p: Vec<Vec<f64>> = freqs
+        .iter()
+        .map(|x| x.iter().map(|row| row.iter().sum()).collect())
+        .collect();
+    let q: Vec<Vec<f64>> = freqs
+        .iter()
+        .map(|x| {
+            (0..n_alleles_b)
+                .map(|k| x.iter().map(|row| row[k]).sum())
+                .collect()
+        })
+        .collect();
+    let mean = |values: &dyn Fn(usize) -> f64| (0..freqs.len()).map(values).sum::<f64>() / n_pops;
+
+    let mut stats = OhtaD {
+        d2_is: 0.0,
+        d2_st: 0.0,
+        d2_is_prime: 0.0,
+        d2_st_prime: 0.0,
+        d2_it: 0.0,
+    };
+    for j in 0..n_alleles_a {
+        for k in 0..n_alleles_b {
+            let mean_p = mean(&|i| p[i][j]);
+            let mean_q = mean(&|i| q[i][k]);
+            let mean_x = mean(&|i| freqs[i][j][k]);
+            stats.d2_is += mean(&|i| (freqs[i][j][k] - p[i][j] * q[i][k]).powi(2));
+            stats.d2_st += mean(&|i| (p[i][j] * q[i][k] - mean_p * mean_q).powi(2));
+            stats.d2_is_prime += mean(&|i| (freqs[i][j][k] - mean_x).powi(2));
+            stats.d2_st_prime += (mean_x - mean_p * mean_q).powi(2);
+            stats.d2_it += mean(&|i| (freqs[i][j][k] - mean_p * mean_q).powi(2));
+        }
+    }
+    Some(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::parse_vcf_str;
+
+    // Pop 1 only has the 0-0 haplotype, S2 being unphased and ignored, and
+    // pop 2 only the 1-1 one.
+    const VCF_LD: &str = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2\tS3\tS4
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT\t0|0\t0/1\t1|1\t1/1
+1\t20\t.\tC\tT\t.\tPASS\t.\tGT\t0|0\t1/0\t1|1\t1/1";
+
+    #[test]
+    fn ohta_components() {
+        let vars = parse_vcf_str(VCF_LD);
+        let pops = vec![vec![0, 1], vec![2, 3]];
+        let stats = ohta_d_stats(&vars[0], &vars[1], &pops).unwrap();
+        // Every pop is in equilibrium, but the total population is not.
+        assert_eq!(
+            stats,
+            OhtaD {
+                d2_is: 0.0,
+                d2_st: 0.75,
+                d2_is_prime: 0.5,
+                d2_st_prime: 0.25,
+                d2_it: 0.75,
+            }
+        );
+
+        assert!(ohta_d_stats(&vars[0], &vars[1], &[vec![1]]).is_none());
+    }
+}
@@ -1,11 +1,60 @@
-use flate2::read::MultiGzDecoder;
+use chrom_order::{ChromKey, ChromRanks};
+use flate2::bufread::MultiGzDecoder;
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::iter::Peekable;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+pub mod annotation;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+mod chrom_order;
+pub mod compare;
+#[cfg(feature = "polars")]
+pub mod dataframe;
+pub mod export;
+pub mod genotypes;
+#[cfg(feature = "hdf5")]
+pub mod hdf5;
+mod header;
+pub mod ld;
+pub mod nei;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod phasing;
+#[cfg(feature = "python")]
+mod python;
+pub mod reference;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod stats;
+pub mod tree;
+pub mod windows;
+
+use annotation::Feature;
+pub use chrom_order::ChromOrder;
+pub use header::{Contig, FieldDefinition, VcfHeader};
 
 const GT_FIELD_ID: &str = "GT";
-const MISSING_ALLELE: i16 = -1;
+const PS_FIELD_ID: &str = "PS";
+/// Integer type of the allele indexes of the genotypes: `i16`, or `i8` with
+/// the `i8` feature to halve the memory used by biallelic data.
+#[cfg(not(feature = "i8"))]
+pub type AlleleInt = i16;
+#[cfg(feature = "i8")]
+pub type AlleleInt = i8;
+
+const MISSING_ALLELE: AlleleInt = -1;
+const NON_REF_ALLELE: &str = "<NON_REF>";
+const MISSING_ALT: &str = ".";
+const DEFAULT_BUFFER_CAPACITY: usize = 1 << 20;
+const GT_CACHE_SIZE: usize = 8;
+#[cfg(feature = "zstd")]
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
 
 #[derive(thiserror::Error, Debug)]
 pub enum VCFParseError {
@@ -29,6 +78,22 @@ pub enum VCFParseError {
     GtOutsideBounds(String),
     #[error("Incorrect allele `{0}` in line: `{1}`")]
     IncorrectAllele(String, String),
+    #[error("Expected at least 8 columns, but found {found} in line: `{line}`")]
+    TooFewColumns { found: usize, line: String },
+    #[error("Expected {expected} genotypes, one per sample, but found {found} in line: `{line}`")]
+    WrongNumberOfGenotypes {
+        expected: usize,
+        found: usize,
+        line: String,
+    },
+    #[error("Allele index too large for the genotype integer type in line: `{0}`")]
+    AlleleIndexTooLarge(String),
+    #[error("Allele index {index} is not below the {n_alleles} alleles in line: `{line}`")]
+    AlleleIndexOutOfRange {
+        index: usize,
+        n_alleles: usize,
+        line: String,
+    },
     #[error("Different ploidies found in line: `{0}`")]
     DifferentPloidiesError(String),
     #[error("Error parsing GTs in line: `{0}`")]
@@ -37,133 +102,843 @@ pub enum VCFParseError {
     InvalidVCFFile(String),
     #[error("File is gzip, but does not start with ##: `{0}`")]
     InvalidGzipVCFFile(String),
+    #[cfg(feature = "zstd")]
+    #[error("File is zstd, but does not start with ##: `{0}`")]
+    InvalidZstdVCFFile(String),
     #[error("First GT `{0}` does not define ploidy in first variant line: `{1}`")]
     FirstGtDoesNotDefinePloidy(String, String),
+    #[error("Unsorted input in chromosome `{chrom}`: position {pos} found after {prev}")]
+    UnsortedInput { chrom: String, pos: u64, prev: u64 },
+    #[error("Unsorted input: chromosome `{chrom}` found after `{prev}`")]
+    UnsortedChromosomes { chrom: String, prev: String },
+    #[error("Phase set `{0}` is not a valid integer in line: `{1}`")]
+    PhaseSetNotInt(String, String),
+    #[error("REF `{found}` at {chrom}:{pos} does not match the reference `{expected}`")]
+    ReferenceMismatch {
+        chrom: String,
+        pos: u64,
+        expected: String,
+        found: String,
+    },
+    #[error("Error reading the reference: {0}")]
+    ReferenceReadError(String),
+    #[error("The genotype of sample {sample_idx} has no GT subfield in line: `{line}`")]
+    GtFieldMissingForSample { sample_idx: usize, line: String },
+    #[error("Error writing the variants: {0}")]
+    WriteError(String),
+}
+
+/// The FILTER column of a record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterStatus {
+    /// `PASS`, the record passed all the filters.
+    Pass,
+    /// `.`, no filters have been applied.
+    Missing,
+    /// The names of the filters that the record failed.
+    Failed(Vec<String>),
+}
+
+impl FilterStatus {
+    fn parse(field: &str) -> FilterStatus {
+        match field {
+            "PASS" => FilterStatus::Pass,
+            "." => FilterStatus::Missing,
+            _ => FilterStatus::Failed(field.split(';').map(|s| s.to_string()).collect()),
+        }
+    }
+
+    fn to_field(&self) -> String {
+        match self {
+            FilterStatus::Pass => "PASS".to_string(),
+            FilterStatus::Missing => ".".to_string(),
+            FilterStatus::Failed(names) => names.join(";"),
+        }
+    }
+}
+
+/// What to do with genotypes in which only some alleles are missing, like `0/.`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HalfCallPolicy {
+    /// Keep the called alleles.
+    #[default]
+    Keep,
+    /// Set every allele to missing.
+    Missing,
+    /// Set the missing alleles to REF.
+    Reference,
+}
+
+impl HalfCallPolicy {
+    fn apply(&self, gt: &mut [AlleleInt]) {
+        let n_missing = gt
+            .iter()
+            .filter(|allele| **allele == MISSING_ALLELE)
+            .count();
+        if n_missing == 0 || n_missing == gt.len() {
+            return;
+        }
+        match self {
+            HalfCallPolicy::Keep => {}
+            HalfCallPolicy::Missing => gt.fill(MISSING_ALLELE),
+            HalfCallPolicy::Reference => gt
+                .iter_mut()
+                .filter(|allele| **allele == MISSING_ALLELE)
+                .for_each(|allele| *allele = 0),
+        }
+    }
+}
+
+/// An allele with its count and frequency among the called alleles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlleleFreq {
+    pub allele: String,
+    pub freq: f64,
+    pub count: u64,
 }
 
-#[derive(Debug)]
+/// Variants are equal, and hash equally, when they are at the same site: the
+/// same chrom, pos, REF and ALTs in any order, see `Variant::same_site`. This
+/// is site identity, not record identity, the rest of the fields, genotypes
+/// included, are ignored; see `Variant::same_record`.
+#[derive(Debug, Clone)]
 pub struct Variant {
     chrom: String,
     pos: u64,
     id: String,
     alleles: Vec<String>,
     qual: f64,
-    filters: Vec<String>,
-    gts: Vec<Vec<i16>>,
+    filter: FilterStatus,
+    /// The raw INFO column.
+    info: String,
+    gts: Vec<Vec<AlleleInt>>,
+    /// Per sample, whether the genotype alleles are separated by `|`.
+    phased: Vec<bool>,
+    /// Samples whose genotype mixes `|` and `/` separators, usually none.
+    mixed_phasing: Vec<usize>,
+    /// Per sample, the PS FORMAT field, empty if the FORMAT has no PS.
+    phase_sets: Vec<Option<u64>>,
     ploidy: u8,
+    /// Fields of an external table attached by `annotate_from_tsv`.
+    annotations: Vec<String>,
+    /// Features of a GFF/GTF overlapping the variant, see `annotate_with_gff`.
+    features: Vec<Feature>,
+    /// Per FORMAT key requested with `VcfReaderBuilder::format_fields`, other
+    /// than GT, its raw value in every sample.
+    format_fields: Vec<(String, Vec<String>)>,
+}
+
+impl PartialEq for Variant {
+    fn eq(&self, other: &Variant) -> bool {
+        self.same_site(other, true)
+    }
+}
+
+impl Eq for Variant {}
+
+impl Hash for Variant {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.chrom.hash(state);
+        self.pos.hash(state);
+        self.ref_allele().hash(state);
+        self.sorted_alt_alleles().hash(state);
+    }
+}
+
+impl Variant {
+    pub fn chrom(&self) -> &str {
+        &self.chrom
+    }
+
+    /// POS, 1-based.
+    pub fn pos(&self) -> u64 {
+        self.pos
+    }
+
+    /// ID, `.` if missing.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// REF followed by the ALTs.
+    pub fn alleles(&self) -> &[String] {
+        &self.alleles
+    }
+
+    /// The names of the failed filters, empty for `PASS` and missing FILTER,
+    /// see `filter_status` to tell them apart.
+    pub fn filters(&self) -> &[String] {
+        match &self.filter {
+            FilterStatus::Failed(names) => names,
+            FilterStatus::Pass | FilterStatus::Missing => &[],
+        }
+    }
+
+    /// Per sample, the allele indexes of its genotype, -1 for missing alleles.
+    pub fn genotypes(&self) -> &[Vec<AlleleInt>] {
+        &self.gts
+    }
+
+    /// The number of alleles of the genotype of the first sample.
+    pub fn ploidy(&self) -> u8 {
+        self.ploidy
+    }
+
+    /// Fields of the row attached by `annotate_from_tsv`, empty if none was.
+    pub fn annotations(&self) -> &[String] {
+        &self.annotations
+    }
+
+    /// Features attached by `annotate_with_gff`, empty if none overlaps.
+    pub fn features(&self) -> &[Feature] {
+        &self.features
+    }
+
+    pub fn filter_status(&self) -> &FilterStatus {
+        &self.filter
+    }
+
+    /// QUAL, 0 if missing.
+    pub fn qual(&self) -> f64 {
+        self.qual
+    }
+
+    /// The value of an INFO key, empty for flags, None if the key is absent.
+    pub fn info(&self, key: &str) -> Option<&str> {
+        if self.info == "." {
+            return None;
+        }
+        self.info
+            .split(';')
+            .find_map(|item| match item.split_once('=') {
+                Some((item_key, value)) if item_key == key => Some(value),
+                None if item == key => Some(""),
+                _ => None,
+            })
+    }
+
+    /// Whether both records have the same chrom, pos and REF, and, if
+    /// `match_alts` is true, the same ALTs in any order.
+    pub fn same_site(&self, other: &Variant, match_alts: bool) -> bool {
+        self.chrom == other.chrom
+            && self.pos == other.pos
+            && self.ref_allele() == other.ref_allele()
+            && (!match_alts || self.sorted_alt_alleles() == other.sorted_alt_alleles())
+    }
+
+    fn sorted_alt_alleles(&self) -> Vec<&str> {
+        let mut alts: Vec<&str> = self.alt_alleles().iter().map(|s| s.as_str()).collect();
+        alts.sort_unstable();
+        alts
+    }
+
+    /// Whether both are the same record: same site, with the alleles in the
+    /// same order, and the same genotypes. Unlike `==`, which only compares
+    /// sites.
+    pub fn same_record(&self, other: &Variant) -> bool {
+        self.pos == other.pos
+            && self.chrom == other.chrom
+            && self.alleles == other.alleles
+            && self.gts == other.gts
+    }
+
+    /// Last position spanned by the record, the INFO `END` or the end of REF.
+    pub fn end(&self) -> u64 {
+        match self.info("END").and_then(|end| end.parse().ok()) {
+            Some(end) => end,
+            None => self.pos + self.alleles[0].len().max(1) as u64 - 1,
+        }
+    }
+
+    /// Like `end`, but never before POS, as the INFO `END` of a malformed
+    /// record can be.
+    pub fn span_end(&self) -> u64 {
+        self.end().max(self.pos)
+    }
+
+    /// Whether the reference span of the record, from POS to `end`, overlaps
+    /// the region from `start` to `end`, 1-based and inclusive.
+    pub fn in_region(&self, chrom: &str, start: u64, end: u64) -> bool {
+        self.chrom == chrom && self.pos <= end && self.end() >= start
+    }
+
+    /// Whether the only ALT is the gVCF `<NON_REF>`, so the record is a reference block.
+    pub fn is_reference_block(&self) -> bool {
+        self.alleles.len() > 1
+            && self.alleles[1..]
+                .iter()
+                .all(|allele| allele == NON_REF_ALLELE)
+    }
+
+    /// The REF allele, empty if the variant has no alleles.
+    ///
+    /// ```
+    /// use nei_rs::read_vcf_file;
+    /// use std::path::PathBuf;
+    ///
+    /// let mut vars = read_vcf_file(&PathBuf::from("tests/data/format_example_4_5.vcf")).unwrap();
+    /// let var = vars.vars_iter.next().unwrap().unwrap();
+    /// assert_eq!(var.ref_allele(), "G");
+    /// ```
+    pub fn ref_allele(&self) -> &str {
+        self.alleles.first().map_or("", |allele| allele.as_str())
+    }
+
+    /// The ALT alleles, empty if there are none.
+    ///
+    /// ```
+    /// use nei_rs::read_vcf_file;
+    /// use std::path::PathBuf;
+    ///
+    /// let mut vars = read_vcf_file(&PathBuf::from("tests/data/format_example_4_5.vcf")).unwrap();
+    /// let var = vars.vars_iter.nth(2).unwrap().unwrap();
+    /// assert_eq!(var.alt_alleles(), ["G", "T"]);
+    /// ```
+    pub fn alt_alleles(&self) -> &[String] {
+        self.alleles.get(1..).unwrap_or(&[])
+    }
+
+    /// Whether the genotype of the sample is phased, all its separators being `|`.
+    pub fn is_phased(&self, sample_idx: usize) -> bool {
+        self.phased[sample_idx]
+    }
+
+    /// The raw value of a FORMAT subfield in every sample, `.` if a sample
+    /// lacks it, or None if it was not requested with
+    /// `VcfReaderBuilder::format_fields` or is not in the FORMAT of the line.
+    pub fn format_field(&self, key: &str) -> Option<&[String]> {
+        self.format_fields
+            .iter()
+            .find(|(field_key, _)| field_key == key)
+            .map(|(_, values)| values.as_slice())
+    }
+
+    /// The PS FORMAT field of the sample, the phase block its genotype belongs
+    /// to, None if it is missing or the FORMAT has no PS.
+    pub fn phase_set(&self, sample_idx: usize) -> Option<u64> {
+        self.phase_sets.get(sample_idx).copied().flatten()
+    }
+
+    /// False if the separators of the genotype of the sample disagree, like
+    /// in `0|1/2|2`, which some tools write by mistake.
+    pub fn is_consistently_phased(&self, sample_idx: usize) -> bool {
+        !self.mixed_phasing.contains(&sample_idx)
+    }
+
+    /// The variant as a VCF line, without the trailing newline.
+    ///
+    /// Only the GT and PS subfields of `format`, and those kept with
+    /// `VcfReaderBuilder::format_fields`, are filled, the rest are `.`.
+    /// A QUAL of 0 is written as missing.
+    pub fn to_vcf_line(&self, format: &str) -> String {
+        let alts = if self.alleles.len() > 1 {
+            self.alleles[1..].join(",")
+        } else {
+            MISSING_ALT.to_string()
+        };
+        let qual = if self.qual == 0.0 {
+            ".".to_string()
+        } else {
+            self.qual.to_string()
+        };
+        let mut fields = vec![
+            self.chrom.clone(),
+            self.pos.to_string(),
+            self.id.clone(),
+            self.alleles[0].clone(),
+            alts,
+            qual,
+            self.filter.to_field(),
+            self.info.clone(),
+            format.to_string(),
+        ];
+        for (sample_idx, gt) in self.gts.iter().enumerate() {
+            let subfields: Vec<String> = format
+                .split(':')
+                .map(|key| match key {
+                    GT_FIELD_ID => {
+                        let separator = if self.phased[sample_idx] { "|" } else { "/" };
+                        gt.iter()
+                            .map(|allele| match *allele {
+                                MISSING_ALLELE => ".".to_string(),
+                                allele => allele.to_string(),
+                            })
+                            .collect::<Vec<String>>()
+                            .join(separator)
+                    }
+                    PS_FIELD_ID if !self.phase_sets.is_empty() => {
+                        match self.phase_set(sample_idx) {
+                            Some(phase_set) => phase_set.to_string(),
+                            None => ".".to_string(),
+                        }
+                    }
+                    _ => match self.format_field(key) {
+                        Some(values) => values[sample_idx].clone(),
+                        None => ".".to_string(),
+                    },
+                })
+                .collect();
+            fields.push(subfields.join(":"));
+        }
+        fields.join("\t")
+    }
+
+    /// Swaps the ancestral allele with REF, so that index 0 is ancestral, and
+    /// recodes the genotypes accordingly.
+    ///
+    /// Alleles are compared ignoring case, as lowercase marks low confidence
+    /// ancestral calls. Returns false, leaving the variant unchanged, if no
+    /// allele matches.
+    pub fn polarize(&mut self, ancestral: &str) -> bool {
+        let ancestral_idx = match self
+            .alleles
+            .iter()
+            .position(|allele| allele.eq_ignore_ascii_case(ancestral))
+        {
+            Some(idx) => idx,
+            None => return false,
+        };
+        if ancestral_idx == 0 {
+            return true;
+        }
+        self.alleles.swap(0, ancestral_idx);
+        let ancestral_idx = ancestral_idx as AlleleInt;
+        for allele in self.gts.iter_mut().flatten() {
+            if *allele == 0 {
+                *allele = ancestral_idx;
+            } else if *allele == ancestral_idx {
+                *allele = 0;
+            }
+        }
+        true
+    }
+
+    /// Number of non-missing alleles across all samples (AN).
+    pub fn allele_number(&self) -> u64 {
+        self.gts
+            .iter()
+            .flatten()
+            .filter(|allele| **allele != MISSING_ALLELE)
+            .count() as u64
+    }
+
+    /// Approximate heap memory owned by the variant, in bytes.
+    pub fn heap_size(&self) -> usize {
+        fn strings_size(strings: &Vec<String>) -> usize {
+            strings.capacity() * std::mem::size_of::<String>()
+                + strings.iter().map(|s| s.capacity()).sum::<usize>()
+        }
+        self.chrom.capacity()
+            + self.id.capacity()
+            + strings_size(&self.alleles)
+            + match &self.filter {
+                FilterStatus::Failed(names) => strings_size(names),
+                _ => 0,
+            }
+            + strings_size(&self.annotations)
+            + self.features.capacity() * std::mem::size_of::<Feature>()
+            + self
+                .features
+                .iter()
+                .map(|feature| feature.heap_size())
+                .sum::<usize>()
+            + self.info.capacity()
+            + self.gts.capacity() * std::mem::size_of::<Vec<AlleleInt>>()
+            + self
+                .gts
+                .iter()
+                .map(|gt| gt.capacity() * std::mem::size_of::<AlleleInt>())
+                .sum::<usize>()
+            + self.phased.capacity()
+            + self.mixed_phasing.capacity() * std::mem::size_of::<usize>()
+            + self.phase_sets.capacity() * std::mem::size_of::<Option<u64>>()
+            + self
+                .format_fields
+                .iter()
+                .map(|(key, values)| {
+                    key.capacity()
+                        + values.capacity() * std::mem::size_of::<String>()
+                        + values.iter().map(String::capacity).sum::<usize>()
+                })
+                .sum::<usize>()
+    }
+
+    /// Counts of each allele among the called alleles of all the samples.
+    pub fn allele_counts(&self) -> Vec<u64> {
+        let samples: Vec<usize> = (0..self.gts.len()).collect();
+        stats::population_allele_counts(self, &samples)
+    }
+
+    /// Count and frequency of every allele, in the order of `alleles`.
+    ///
+    /// Frequencies are NaN if no allele is called.
+    pub fn allele_freq_table(&self) -> Vec<AlleleFreq> {
+        let counts = self.allele_counts();
+        let total: u64 = counts.iter().sum();
+        self.alleles
+            .iter()
+            .zip(counts)
+            .map(|(allele, count)| AlleleFreq {
+                allele: allele.clone(),
+                freq: count as f64 / total as f64,
+                count,
+            })
+            .collect()
+    }
+
+    /// Count of the second most common allele (MAC), 0 for monomorphic sites.
+    pub fn minor_allele_count(&self) -> u64 {
+        let mut counts = self.allele_counts();
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+        counts.get(1).copied().unwrap_or(0)
+    }
+
+    /// Frequencies of the alleles among all the called alleles, empty if none is called.
+    pub fn allele_frequencies(&self) -> Vec<f64> {
+        let samples: Vec<usize> = (0..self.gts.len()).collect();
+        stats::population_allele_freqs(self, &samples)
+    }
+
+    /// Effective number of alleles, `1 / sum(p_i^2)`, NaN if no allele is called.
+    pub fn effective_num_alleles(&self) -> f64 {
+        let freqs = self.allele_frequencies();
+        if freqs.is_empty() {
+            return f64::NAN;
+        }
+        1.0 / freqs.iter().map(|freq| freq * freq).sum::<f64>()
+    }
+
+    /// Number of samples with at least two different called alleles.
+    pub fn het_count(&self) -> u64 {
+        self.gts
+            .iter()
+            .filter(|gt| {
+                let mut called = gt.iter().filter(|allele| **allele != MISSING_ALLELE);
+                called
+                    .next()
+                    .is_some_and(|first| called.any(|allele| allele != first))
+            })
+            .count() as u64
+    }
+
+    /// Number of samples with every allele called as REF.
+    pub fn hom_ref_count(&self) -> u64 {
+        self.count_homozygous(0)
+    }
+
+    /// Number of samples with every allele called as the first ALT, meant for
+    /// biallelic sites.
+    pub fn hom_alt_count(&self) -> u64 {
+        self.count_homozygous(1)
+    }
+
+    fn count_homozygous(&self, allele: AlleleInt) -> u64 {
+        self.gts
+            .iter()
+            .filter(|gt| !gt.is_empty() && gt.iter().all(|gt_allele| *gt_allele == allele))
+            .count() as u64
+    }
+
+    /// Shannon diversity index, `-sum(p_i * ln p_i)` with `0 * ln 0 = 0`, NaN
+    /// if no allele is called.
+    pub fn shannon_diversity(&self) -> f64 {
+        let freqs = self.allele_frequencies();
+        if freqs.is_empty() {
+            return f64::NAN;
+        }
+        -freqs
+            .iter()
+            .filter(|freq| **freq > 0.0)
+            .map(|freq| freq * freq.ln())
+            .sum::<f64>()
+    }
+
+    /// Number of samples with every allele of their genotype called.
+    pub fn called_samples(&self) -> u64 {
+        self.gts
+            .iter()
+            .filter(|gt| gt.iter().all(|allele| *allele != MISSING_ALLELE))
+            .count() as u64
+    }
 }
 
 struct GtFormatCache {
     gt_string: String,
     gt_format_idxs: HashMap<String, usize>,
     gt_field_idx: usize,
+    ps_field_idx: Option<usize>,
     num_samples: usize,
     ploidy: u8,
+    mixed_ploidy: bool,
+    half_call_policy: HalfCallPolicy,
+    broadcast_single_genotype: bool,
+    /// FORMAT keys to keep, besides GT.
+    format_fields: Vec<String>,
+    parsed_gt_cache: Option<ParsedGtCache>,
 }
 
-fn get_ploidy_form_first_gt(
-    gt: &str,
+/// Least recently used GT strings and their alleles, most recent first.
+///
+/// Small enough for a linear scan, which is what makes it cheaper than parsing.
+struct ParsedGtCache {
+    entries: Vec<(String, Vec<AlleleInt>)>,
+}
+
+impl ParsedGtCache {
+    fn new() -> ParsedGtCache {
+        ParsedGtCache {
+            entries: Vec::with_capacity(GT_CACHE_SIZE),
+        }
+    }
+
+    fn parse_gt(
+        &mut self,
+        gt: &str,
+        sample_idx: usize,
+        parsed_gts: &mut [Vec<AlleleInt>],
+        line: &str,
+    ) -> Result<u8, VCFParseError> {
+        if let Some(idx) = self
+            .entries
+            .iter()
+            .position(|(cached_gt, _)| cached_gt == gt)
+        {
+            self.entries[..=idx].rotate_right(1);
+            parsed_gts[sample_idx].copy_from_slice(&self.entries[0].1);
+            return Ok(parsed_gts[sample_idx].len() as u8);
+        }
+        let ploidy = parse_gt(gt, sample_idx, parsed_gts, line)?;
+        // Only complete genotypes are cached, the rest fail the ploidy check anyway.
+        if ploidy as usize == parsed_gts[sample_idx].len() {
+            if self.entries.len() == GT_CACHE_SIZE {
+                self.entries.pop();
+            }
+            self.entries
+                .insert(0, (gt.to_string(), parsed_gts[sample_idx].clone()));
+        }
+        Ok(ploidy)
+    }
+}
+
+fn genotype_is_complete(gt: &[AlleleInt], ploidy: u8) -> bool {
+    gt.len() == ploidy as usize && gt.iter().all(|allele| *allele != MISSING_ALLELE)
+}
+
+/// The ploidy of the first genotype with all its alleles called, or of the
+/// first genotype if no sample of the line has one.
+fn get_ploidy_from_first_called_gt(
+    gts: &[&str],
     gt_format_cache: &mut GtFormatCache,
+    line: &str,
 ) -> Result<u8, VCFParseError> {
-    let gt = get_gt_item_from_gt_string(gt, gt_format_cache)?;
-    let alleles: Vec<&str> = gt.split(|c| c == '/' || c == '|').collect();
-    let ploidy = alleles.len();
-    Ok(ploidy as u8)
+    let mut first_ploidy = None;
+    for (sample_idx, gt) in gts.iter().enumerate() {
+        let gt = get_gt_item_from_gt_string(gt, sample_idx, gt_format_cache, line)?;
+        let alleles: Vec<&str> = gt.split(['/', '|']).collect();
+        if alleles.iter().all(|allele| *allele != ".") {
+            return Ok(alleles.len() as u8);
+        }
+        first_ploidy.get_or_insert(alleles.len() as u8);
+    }
+    first_ploidy.ok_or(VCFParseError::NoGenotypeFormatDefinition(line.to_string()))
 }
 
-fn parse_gt<'a>(
-    gt: &'a str,
+fn parse_gt(
+    gt: &str,
     sample_idx: usize,
-    parsed_gts: &mut Vec<Vec<i16>>,
-    line: &String,
+    parsed_gts: &mut [Vec<AlleleInt>],
+    line: &str,
 ) -> Result<u8, VCFParseError> {
+    let ploidy = parsed_gts[sample_idx].len();
     if gt == "0/0" {
         return Ok(2);
-    } else if gt == "1/1" {
+    } else if gt == "1/1" && ploidy == 2 {
         parsed_gts[sample_idx][0] = 1;
         parsed_gts[sample_idx][1] = 1;
         return Ok(2);
+    } else if gt == "." {
+        // A missing genotype of any ploidy.
+        parsed_gts[sample_idx].fill(MISSING_ALLELE);
+        return Ok(ploidy as u8);
     }
 
-    let mut allele = 0;
+    let mut allele: AlleleInt = 0;
     let mut ploidy_idx = 0;
     let mut allele_was_missing = false;
     for chr in gt.bytes() {
-        allele *= 10;
-        let digit = chr & 0b0000_1111;
-        if digit < 10 {
-            allele += digit as i16;
-        } else if digit == 12 || digit == 15 {
-            // chr is / or |
-            parsed_gts[sample_idx][ploidy_idx] = allele;
-            allele = 0;
-            ploidy_idx += 1;
-            allele_was_missing = false;
-        } else if digit == 14 && allele == 0 {
-            // chr is .
-            parsed_gts[sample_idx][ploidy_idx] = MISSING_ALLELE;
-            allele_was_missing = true;
-            ploidy_idx += 1;
-        } else {
-            return Err(VCFParseError::IncorrectAllele(
-                chr.to_string(),
-                line.to_string(),
-            ));
+        match chr {
+            b'0'..=b'9' if !allele_was_missing => {
+                allele = allele
+                    .checked_mul(10)
+                    .and_then(|allele| allele.checked_add((chr - b'0') as AlleleInt))
+                    .ok_or_else(|| VCFParseError::AlleleIndexTooLarge(line.to_string()))?;
+            }
+            b'/' | b'|' => {
+                if ploidy_idx + 1 >= ploidy {
+                    return Err(VCFParseError::DifferentPloidiesError(line.to_string()));
+                }
+                parsed_gts[sample_idx][ploidy_idx] = allele;
+                allele = 0;
+                ploidy_idx += 1;
+                allele_was_missing = false;
+            }
+            b'.' if allele == 0 => {
+                allele = MISSING_ALLELE;
+                allele_was_missing = true;
+            }
+            _ => {
+                return Err(VCFParseError::IncorrectAllele(
+                    (chr as char).to_string(),
+                    line.to_string(),
+                ))
+            }
         }
     }
-    if !allele_was_missing {
-        parsed_gts[sample_idx][ploidy_idx] = allele
-    };
+    parsed_gts[sample_idx][ploidy_idx] = allele;
     Ok((ploidy_idx + 1) as u8)
 }
 
 fn get_gt_item_from_gt_string<'a>(
     gt_str: &'a str,
+    sample_idx: usize,
     gt_format_cache: &mut GtFormatCache,
+    line: &str,
 ) -> Result<&'a str, VCFParseError> {
     let desired_field_idx = gt_format_cache.gt_field_idx;
-    let mut idx = 0 as usize;
-    for gt_item in gt_str.split(":") {
+    for (idx, gt_item) in gt_str.split(':').enumerate() {
         if idx == desired_field_idx {
             return Ok(gt_item);
         }
-        idx += 1;
     }
-    Err(VCFParseError::NoGenotypeFormatDefinition(
-        gt_str.to_string(),
-    ))
+    Err(VCFParseError::GtFieldMissingForSample {
+        sample_idx,
+        line: line.to_string(),
+    })
+}
+
+/// The PS of a sample, None if it is missing.
+fn parse_phase_set(
+    gt_str: &str,
+    ps_field_idx: usize,
+    line: &str,
+) -> Result<Option<u64>, VCFParseError> {
+    match gt_str.split(':').nth(ps_field_idx) {
+        None | Some(".") => Ok(None),
+        Some(phase_set) => match phase_set.parse::<u64>() {
+            Ok(phase_set) => Ok(Some(phase_set)),
+            Err(_) => Err(VCFParseError::PhaseSetNotInt(
+                phase_set.to_string(),
+                line.to_string(),
+            )),
+        },
+    }
 }
 
+type ParsedGts = (Vec<Vec<AlleleInt>>, Vec<bool>, Vec<usize>, Vec<Option<u64>>);
+
 fn parse_gts(
     gts: std::slice::Iter<&str>,
+    n_alleles: usize,
     gt_format_cache: &mut GtFormatCache,
     line: &String,
-) -> Result<Vec<Vec<i16>>, VCFParseError> {
+) -> Result<ParsedGts, VCFParseError> {
     let mut parsed_gts =
         vec![vec![0; gt_format_cache.ploidy as usize]; gt_format_cache.num_samples];
+    let mut phased = vec![false; gt_format_cache.num_samples];
+    let mut mixed_phasing = Vec::new();
+    let mut phase_sets = Vec::new();
 
-    let mut sample_idx = 0;
-    for gt_str in gts {
-        let gt = get_gt_item_from_gt_string(gt_str, gt_format_cache)?;
+    let broadcast = gts.len() == 1 && gt_format_cache.num_samples > 1;
 
-        let this_ploidy = match parse_gt(gt, sample_idx, &mut parsed_gts, line) {
-            Ok(alleles) => alleles,
-            Err(e) => return Err(e),
+    for (sample_idx, gt_str) in gts.enumerate() {
+        let gt = get_gt_item_from_gt_string(gt_str, sample_idx, gt_format_cache, line)?;
+        if gt_format_cache.mixed_ploidy {
+            parsed_gts[sample_idx].resize(gt.split(['/', '|']).count(), 0);
+        }
+
+        let this_ploidy = match &mut gt_format_cache.parsed_gt_cache {
+            Some(cache) => cache.parse_gt(gt, sample_idx, &mut parsed_gts, line)?,
+            None => parse_gt(gt, sample_idx, &mut parsed_gts, line)?,
         };
+        if let Some(allele) = parsed_gts[sample_idx]
+            .iter()
+            .find(|allele| **allele != MISSING_ALLELE && **allele as usize >= n_alleles)
+        {
+            return Err(VCFParseError::AlleleIndexOutOfRange {
+                index: *allele as usize,
+                n_alleles,
+                line: line.to_string(),
+            });
+        }
+        gt_format_cache
+            .half_call_policy
+            .apply(&mut parsed_gts[sample_idx]);
+        phased[sample_idx] = gt.contains('|') && !gt.contains('/');
+        if gt.contains('|') && gt.contains('/') {
+            mixed_phasing.push(sample_idx);
+        }
+        if let Some(ps_field_idx) = gt_format_cache.ps_field_idx {
+            phase_sets.push(parse_phase_set(gt_str, ps_field_idx, line)?);
+        }
 
-        if gt_format_cache.ploidy != this_ploidy as u8 {
+        if gt_format_cache.ploidy != this_ploidy && !gt_format_cache.mixed_ploidy {
             return Err(VCFParseError::DifferentPloidiesError(line.to_string()));
         }
-        sample_idx += 1;
     }
-    Ok(parsed_gts)
+    if broadcast {
+        let num_samples = gt_format_cache.num_samples;
+        let gt = parsed_gts.swap_remove(0);
+        parsed_gts = vec![gt; num_samples];
+        phased = vec![phased[0]; num_samples];
+        if !mixed_phasing.is_empty() {
+            mixed_phasing = (0..num_samples).collect();
+        }
+        if let Some(phase_set) = phase_sets.first().copied() {
+            phase_sets.resize(num_samples, phase_set);
+        }
+    }
+    Ok((parsed_gts, phased, mixed_phasing, phase_sets))
+}
+
+/// The raw values of the kept FORMAT keys found in the FORMAT of the line.
+fn parse_format_fields(
+    gts: &[&str],
+    gt_format_cache: &GtFormatCache,
+) -> Vec<(String, Vec<String>)> {
+    let num_samples = gt_format_cache.num_samples;
+    gt_format_cache
+        .format_fields
+        .iter()
+        .filter_map(|key| {
+            let field_idx = *gt_format_cache.gt_format_idxs.get(key)?;
+            let mut values: Vec<String> = gts
+                .iter()
+                .map(|gt| gt.split(':').nth(field_idx).unwrap_or(".").to_string())
+                .collect();
+            if values.len() == 1 && num_samples > 1 {
+                values = vec![values.swap_remove(0); num_samples];
+            }
+            Some((key.clone(), values))
+        })
+        .collect()
 }
 
 fn parse_variant_line(
     line: String,
     gt_format_cache: &mut GtFormatCache,
+    lenient_whitespace: bool,
 ) -> Result<Variant, VCFParseError> {
-    let fields = line.split("\t").collect::<Vec<&str>>();
+    let fields = if lenient_whitespace {
+        line.split_whitespace().collect::<Vec<&str>>()
+    } else {
+        line.split("\t").collect::<Vec<&str>>()
+    };
+    if fields.len() < 8 {
+        return Err(VCFParseError::TooFewColumns {
+            found: fields.len(),
+            line,
+        });
+    }
 
     let pos = match fields[1].parse::<u64>() {
         Ok(pos) => pos,
@@ -172,21 +947,52 @@ fn parse_variant_line(
 
     let mut alleles = Vec::new();
     alleles.push(fields[3].to_string());
-    alleles.extend(fields[4].split(",").map(|s| s.to_string()));
+    // A bare `.` means no ALT, but in a list it keeps the place of its allele index.
+    if fields[4] != MISSING_ALT {
+        alleles.extend(fields[4].split(",").map(|s| s.to_string()));
+    }
 
-    let qual: f64;
-    if fields[5] == "." {
-        qual = 0.0;
+    let qual = if fields[5] == "." {
+        0.0
     } else {
-        qual = match fields[5].parse::<f64>() {
+        match fields[5].parse::<f64>() {
             Ok(pos) => pos,
             Err(_) => return Err(VCFParseError::QualNotFloat(line)),
         }
     };
 
-    let mut filters = Vec::new();
-    if fields[6] != "PASS" {
-        filters.extend(fields[6].split(";").map(|s| s.to_string()));
+    let filter = FilterStatus::parse(fields[6]);
+
+    if gt_format_cache.num_samples == 0 {
+        // Sites-only files, like dbSNP, with or without FORMAT.
+        return Ok(Variant {
+            chrom: fields[0].to_string(),
+            pos,
+            id: fields[2].to_string(),
+            alleles,
+            qual,
+            filter,
+            info: fields[7].to_string(),
+            gts: Vec::new(),
+            phased: Vec::new(),
+            mixed_phasing: Vec::new(),
+            phase_sets: Vec::new(),
+            ploidy: gt_format_cache.ploidy,
+            annotations: Vec::new(),
+            features: Vec::new(),
+            format_fields: Vec::new(),
+        });
+    }
+    // Checked before reading any genotype, as ragged lines would be indexed
+    // out of bounds.
+    let num_columns = fields.len().saturating_sub(9);
+    let broadcast = gt_format_cache.broadcast_single_genotype && num_columns == 1;
+    if num_columns != gt_format_cache.num_samples && !broadcast {
+        return Err(VCFParseError::WrongNumberOfGenotypes {
+            expected: gt_format_cache.num_samples,
+            found: num_columns,
+            line,
+        });
     }
 
     let gt_format_str = fields[8].to_string();
@@ -202,21 +1008,24 @@ fn parse_variant_line(
             Some(idx) => *idx,
             None => return Err(VCFParseError::GenotypeNotFoundInFormatDefinition(line)),
         };
+        gt_format_cache.ps_field_idx = gt_format_cache.gt_format_idxs.get(PS_FIELD_ID).copied();
     }
 
     if gt_format_cache.ploidy == 0 {
-        gt_format_cache.ploidy = match get_ploidy_form_first_gt(&fields[9], gt_format_cache) {
-            Ok(ploidy) => ploidy,
-            Err(_) => {
-                return Err(VCFParseError::FirstGtDoesNotDefinePloidy(
-                    fields[9].to_string(),
-                    line.to_string(),
-                ))
-            }
-        };
+        gt_format_cache.ploidy =
+            match get_ploidy_from_first_called_gt(&fields[9..], gt_format_cache, &line) {
+                Ok(ploidy) => ploidy,
+                Err(_) => {
+                    return Err(VCFParseError::FirstGtDoesNotDefinePloidy(
+                        fields[9].to_string(),
+                        line.to_string(),
+                    ))
+                }
+            };
     }
 
-    let gts = parse_gts(fields[9..].iter(), gt_format_cache, &line)?;
+    let (gts, phased, mixed_phasing, phase_sets) =
+        parse_gts(fields[9..].iter(), alleles.len(), gt_format_cache, &line)?;
 
     let ploidy = gts[0].len() as u8;
 
@@ -226,17 +1035,196 @@ fn parse_variant_line(
         id: fields[2].to_string(),
         alleles,
         qual,
-        filters,
+        filter,
+        info: fields[7].to_string(),
         gts,
-        ploidy: ploidy,
+        phased,
+        mixed_phasing,
+        phase_sets,
+        ploidy,
+        annotations: Vec::new(),
+        features: Vec::new(),
+        format_fields: parse_format_fields(&fields[9..], gt_format_cache),
     };
     Ok(var)
 }
 
+pub type VariantsIter<'a> = Peekable<Box<dyn Iterator<Item = Result<Variant, VCFParseError>> + 'a>>;
+
 pub struct Variants<'a> {
     pub samples: Vec<String>,
-    pub vars_iter: Box<dyn Iterator<Item = Result<Variant, VCFParseError>> + 'a>,
+    pub vars_iter: VariantsIter<'a>,
     pub ploidy: u8,
+    /// False if the ploidy was guessed from missing genotypes, because no
+    /// sample of the first variant had all its alleles called.
+    pub ploidy_confident: bool,
+}
+
+impl<'a> Variants<'a> {
+    fn new(
+        samples: Vec<String>,
+        vars_iter: impl Iterator<Item = Result<Variant, VCFParseError>> + 'a,
+        ploidy: u8,
+        ploidy_confident: bool,
+    ) -> Variants<'a> {
+        let vars_iter: Box<dyn Iterator<Item = Result<Variant, VCFParseError>> + 'a> =
+            Box::new(vars_iter);
+        Variants {
+            samples,
+            vars_iter: vars_iter.peekable(),
+            ploidy,
+            ploidy_confident,
+        }
+    }
+
+    /// The next variant without consuming it, the first one if none has been read.
+    pub fn peek_first(&mut self) -> Option<Result<&Variant, &VCFParseError>> {
+        self.vars_iter.peek().map(|var| var.as_ref())
+    }
+
+    /// Renames the chromosome of every variant.
+    pub fn map_chrom_names(self, f: impl Fn(&str) -> String + 'a) -> Variants<'a> {
+        // Consecutive variants usually share the chromosome, so reuse the last name.
+        let mut last_name: Option<(String, String)> = None;
+        let vars_iter = self.vars_iter.map(move |var| {
+            let mut var = var?;
+            match &last_name {
+                Some((old, new)) if *old == var.chrom => var.chrom.clone_from(new),
+                _ => {
+                    let new = f(&var.chrom);
+                    let old = std::mem::replace(&mut var.chrom, new.clone());
+                    last_name = Some((old, new));
+                }
+            }
+            Ok(var)
+        });
+        Variants::new(self.samples, vars_iter, self.ploidy, self.ploidy_confident)
+    }
+
+    /// Renames `chr20` to `20`.
+    pub fn strip_chr_prefix(self) -> Variants<'a> {
+        self.map_chrom_names(|chrom| chrom.strip_prefix("chr").unwrap_or(chrom).to_string())
+    }
+
+    /// Renames `20` to `chr20`, leaving the names that already have the prefix.
+    pub fn add_chr_prefix(self) -> Variants<'a> {
+        self.map_chrom_names(|chrom| {
+            if chrom.starts_with("chr") {
+                chrom.to_string()
+            } else {
+                format!("chr{chrom}")
+            }
+        })
+    }
+
+    /// Keeps the variants for which the predicate is true, and every parse error.
+    pub fn filter_variants(self, predicate: impl Fn(&Variant) -> bool + 'a) -> Variants<'a> {
+        let vars_iter = self.vars_iter.filter(move |var| match var {
+            Ok(var) => predicate(var),
+            Err(_) => true,
+        });
+        Variants::new(self.samples, vars_iter, self.ploidy, self.ploidy_confident)
+    }
+
+    /// Keeps the variants with a minor allele count of at least `min_mac`.
+    pub fn filter_mac(self, min_mac: u64) -> Variants<'a> {
+        self.filter_variants(move |var| var.minor_allele_count() >= min_mac)
+    }
+
+    /// Keeps a variant only if it is at least `min_distance` bp away from the
+    /// last kept one of the same chromosome. The input must be sorted.
+    pub fn thin(self, min_distance: u64) -> Variants<'a> {
+        let mut last_kept: Option<(String, u64)> = None;
+        let vars_iter = self.vars_iter.filter(move |var| {
+            let var = match var {
+                Ok(var) => var,
+                Err(_) => return true,
+            };
+            let keep = match &last_kept {
+                Some((chrom, pos)) if *chrom == var.chrom => {
+                    var.pos.saturating_sub(*pos) >= min_distance
+                }
+                _ => true,
+            };
+            if keep {
+                last_kept = Some((var.chrom.clone(), var.pos));
+            }
+            keep
+        });
+        Variants::new(self.samples, vars_iter, self.ploidy, self.ploidy_confident)
+    }
+
+    /// Drops a variant when its chromosome, position, alleles and genotypes
+    /// are equal to those of the previous one, a duplicated record.
+    pub fn dedup_identical(self) -> Variants<'a> {
+        let mut previous: Option<Variant> = None;
+        let vars_iter = self.vars_iter.filter(move |var| {
+            let var = match var {
+                Ok(var) => var,
+                Err(_) => return true,
+            };
+            let is_duplicated = previous
+                .as_ref()
+                .is_some_and(|previous| previous.same_record(var));
+            if !is_duplicated {
+                previous = Some(var.clone());
+            }
+            !is_duplicated
+        });
+        Variants::new(self.samples, vars_iter, self.ploidy, self.ploidy_confident)
+    }
+
+    /// Keeps the biallelic variants, returning a counter of the dropped ones,
+    /// multiallelic or without ALT, that is updated as the variants are read.
+    ///
+    /// The counter uses relaxed ordering: read it after the iteration, or from
+    /// the iterating thread.
+    pub fn keep_biallelic(self) -> (Variants<'a>, Arc<AtomicU64>) {
+        let n_dropped = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&n_dropped);
+        let vars = self.filter_variants(move |var| {
+            let is_biallelic = var.alleles.len() == 2;
+            if !is_biallelic {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+            is_biallelic
+        });
+        (vars, n_dropped)
+    }
+
+    /// Polarizes every variant by its INFO `AA`, skipping those in which it
+    /// is absent or matches no allele.
+    pub fn polarize_from_aa(self) -> Variants<'a> {
+        let vars_iter = self.vars_iter.filter_map(|var| {
+            let mut var = match var {
+                Ok(var) => var,
+                Err(e) => return Some(Err(e)),
+            };
+            let ancestral = var.info("AA")?.to_string();
+            var.polarize(&ancestral).then_some(Ok(var))
+        });
+        Variants::new(self.samples, vars_iter, self.ploidy, self.ploidy_confident)
+    }
+
+    /// Stops after the first `n` variants.
+    pub fn take(self, n: usize) -> Variants<'a> {
+        Variants::new(
+            self.samples,
+            self.vars_iter.take(n),
+            self.ploidy,
+            self.ploidy_confident,
+        )
+    }
+
+    /// Skips the first `n` variants.
+    pub fn skip(self, n: usize) -> Variants<'a> {
+        Variants::new(
+            self.samples,
+            self.vars_iter.skip(n),
+            self.ploidy,
+            self.ploidy_confident,
+        )
+    }
 }
 
 fn read_sample_line(line: &str) -> Result<Vec<String>, VCFParseError> {
@@ -244,132 +1232,513 @@ fn read_sample_line(line: &str) -> Result<Vec<String>, VCFParseError> {
         return Err(VCFParseError::InvalidSampleLine(line.to_string()));
     }
 
-    let samples = line.split("\t").skip(9).map(|s| s.to_string()).collect();
+    let samples = line
+        .trim_end()
+        .split('\t')
+        .skip(9)
+        .map(|s| s.to_string())
+        .collect();
     Ok(samples)
 }
 
-fn parse_vcf_buffer<'a, T: Read + 'a>(
-    mut file: BufReader<T>,
-) -> Result<Variants<'a>, VCFParseError> {
-    let samples;
-    loop {
-        let mut line = String::new();
-        match file.read_line(&mut line) {
-            Ok(0) => return Err(VCFParseError::EmptyFile),
-            Ok(_) => (),
-            Err(_) => return Err(VCFParseError::ReadLineError(0)),
+/// Checks the positions within every chromosome and, with `chrom_ranks`, the
+/// order of the chromosomes.
+fn check_sorted<'a>(
+    vars_iter: impl Iterator<Item = Result<Variant, VCFParseError>> + 'a,
+    mut chrom_ranks: Option<ChromRanks>,
+) -> impl Iterator<Item = Result<Variant, VCFParseError>> + 'a {
+    let mut last_positions: HashMap<String, u64> = HashMap::new();
+    let mut last_chrom: Option<(String, ChromKey)> = None;
+    vars_iter.map(move |var| {
+        let var = var?;
+        if let Some(chrom_ranks) = &mut chrom_ranks {
+            match &last_chrom {
+                Some((prev, _)) if *prev == var.chrom => {}
+                Some((prev, prev_key)) if chrom_ranks.key(&var.chrom) < *prev_key => {
+                    return Err(VCFParseError::UnsortedChromosomes {
+                        chrom: var.chrom,
+                        prev: prev.clone(),
+                    });
+                }
+                _ => last_chrom = Some((var.chrom.clone(), chrom_ranks.key(&var.chrom))),
+            }
         }
-        if line.starts_with("##") {
-        } else if line.starts_with("#CHROM") {
-            samples = read_sample_line(&line)?;
-            break;
+        if let Some(prev) = last_positions.get_mut(&var.chrom) {
+            if var.pos < *prev {
+                return Err(VCFParseError::UnsortedInput {
+                    chrom: var.chrom,
+                    pos: var.pos,
+                    prev: *prev,
+                });
+            }
+            *prev = var.pos;
         } else {
-            return Err(VCFParseError::InvalidSampleLine(line));
+            last_positions.insert(var.chrom.clone(), var.pos);
         }
-    }
+        Ok(var)
+    })
+}
+
+fn parse_vcf_buffer<'a, T: BufRead + 'a>(
+    mut file: T,
+    options: &VcfReaderBuilder,
+) -> Result<Variants<'a>, VCFParseError> {
+    let header = header::read_header(&mut file)?;
+    let chrom_ranks = options.chrom_order.as_ref().map(|order| {
+        ChromRanks::new(
+            order,
+            header.contigs.iter().map(|contig| contig.id.as_str()),
+        )
+    });
+    let samples = header.samples;
 
     let mut gt_format_cache = GtFormatCache {
         gt_string: "".to_string(),
         gt_format_idxs: HashMap::new(),
         gt_field_idx: 0,
+        ps_field_idx: None,
         num_samples: samples.len(),
-        ploidy: 0,
+        ploidy: options.ploidy.unwrap_or(0),
+        mixed_ploidy: options.mixed_ploidy,
+        half_call_policy: options.half_call_policy,
+        broadcast_single_genotype: options.broadcast_single_genotype,
+        format_fields: options
+            .format_fields
+            .iter()
+            .filter(|key| *key != GT_FIELD_ID)
+            .cloned()
+            .collect(),
+        parsed_gt_cache: options.cache_genotypes.then(ParsedGtCache::new),
     };
 
-    let mut vars_iter = file
-        .lines()
-        .map(move |line_res| {
-            let line = match line_res {
-                Ok(line) => line,
-                Err(_) => return Err(VCFParseError::ReadLineError(0)),
-            };
-            parse_variant_line(line, &mut gt_format_cache)
-        })
-        .peekable();
-
-    let first_var = match vars_iter.peek() {
-        Some(Ok(var)) => Variant {
-            chrom: var.chrom.clone(),
-            pos: var.pos,
-            id: var.id.clone(),
-            alleles: var.alleles.clone(),
-            qual: var.qual,
-            filters: var.filters.clone(),
-            gts: var.gts.clone(),
-            ploidy: var.ploidy,
-        },
-        Some(Err(_)) => return Err(VCFParseError::NoVariantsError),
-        None => return Err(VCFParseError::EmptyFile),
+    let lenient_whitespace = options.lenient_whitespace;
+    let vars_iter = file.lines().map(move |line_res| {
+        let line = match line_res {
+            Ok(line) => line,
+            Err(_) => return Err(VCFParseError::ReadLineError(0)),
+        };
+        parse_variant_line(line, &mut gt_format_cache, lenient_whitespace)
+    });
+    let mut vars = if options.require_sorted {
+        Variants::new(samples, check_sorted(vars_iter, chrom_ranks), 0, true)
+    } else {
+        Variants::new(samples, vars_iter, 0, true)
     };
-
-    let vars = Variants {
-        samples: samples,
-        vars_iter: Box::new(vars_iter),
-        ploidy: first_var.ploidy,
+    (vars.ploidy, vars.ploidy_confident) = match vars.vars_iter.peek() {
+        Some(Ok(var)) => {
+            let has_called_gt = var
+                .gts
+                .iter()
+                .any(|gt| genotype_is_complete(gt, var.ploidy));
+            let is_sites_only = var.gts.is_empty();
+            (
+                var.ploidy,
+                options.ploidy.is_some() || has_called_gt || is_sites_only,
+            )
+        }
+        Some(Err(_)) => return Err(vars.vars_iter.next().unwrap().unwrap_err()),
+        None if options.allow_empty => (options.ploidy.unwrap_or(0), true),
+        None => return Err(VCFParseError::NoVariantsError),
     };
 
-    return Ok(vars);
+    Ok(vars)
+}
+
+/// Folds every variant into an accumulator, stopping at the first parse error.
+///
+/// ```
+/// use nei_rs::{read_vcf_file, reduce_variants};
+/// use std::path::PathBuf;
+///
+/// let fpath = PathBuf::from("tests/data/format_example_4_5.vcf");
+/// let vars = read_vcf_file(&fpath).unwrap();
+/// let n_vars = reduce_variants(vars, 0, |n_vars, _var| n_vars + 1).unwrap();
+/// assert_eq!(n_vars, 6);
+/// ```
+pub fn reduce_variants<S, F>(vars: Variants, init: S, mut f: F) -> Result<S, VCFParseError>
+where
+    F: FnMut(S, &Variant) -> S,
+{
+    let mut state = init;
+    for var in vars.vars_iter {
+        state = f(state, &var?);
+    }
+    Ok(state)
+}
+
+/// SplitMix64, a small seedable generator, good enough for sampling.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`, with a negligible bias for any `n` far from 2^64.
+    fn below(&mut self, n: u64) -> u64 {
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+}
+
+/// Draws `k` variants uniformly at random, by reservoir sampling, in a
+/// single pass that only keeps `k` variants in memory.
+///
+/// The same seed gives the same sample. The variants are returned in the
+/// order of the file, all of them if there are less than `k`.
+pub fn sample_variants(vars: Variants, k: usize, seed: u64) -> Result<Vec<Variant>, VCFParseError> {
+    let mut rng = SplitMix64(seed);
+    let mut reservoir: Vec<(usize, Variant)> = Vec::with_capacity(k);
+    for (var_idx, var) in vars.vars_iter.enumerate() {
+        let var = var?;
+        if reservoir.len() < k {
+            reservoir.push((var_idx, var));
+            continue;
+        }
+        let slot = rng.below(var_idx as u64 + 1) as usize;
+        if slot < k {
+            reservoir[slot] = (var_idx, var);
+        }
+    }
+    reservoir.sort_unstable_by_key(|(var_idx, _)| *var_idx);
+    Ok(reservoir.into_iter().map(|(_, var)| var).collect())
 }
 
-#[derive(PartialEq)]
+#[derive(Debug, PartialEq)]
 pub enum VcfFileKind {
     PlainTextVcf,
     GzippedVcf,
+    #[cfg(feature = "zstd")]
+    ZstdVcf,
 }
 
-pub fn guess_vcf_file_kind(fpath: &PathBuf) -> Result<VcfFileKind, Box<dyn std::error::Error>> {
-    let mut file = match File::open(fpath.clone()) {
-        Ok(file) => file,
-        Err(e) => return Err(Box::new(e)),
-    };
+fn sniff_kind<R: Read + Seek>(
+    reader: &mut R,
+    source: &str,
+) -> Result<VcfFileKind, Box<dyn std::error::Error>> {
+    let start = reader.stream_position()?;
     let mut buffer = vec![0; 2];
-    file.read_exact(&mut buffer)?;
+    reader.read_exact(&mut buffer)?;
+    reader.seek(SeekFrom::Start(start))?;
 
     if buffer == [0x23, 0x23] {
         return Ok(VcfFileKind::PlainTextVcf);
     }
+    #[cfg(feature = "zstd")]
+    if buffer == ZSTD_MAGIC[..2] {
+        return sniff_zstd(reader, start, source);
+    }
     if buffer != [0x1f, 0x8b] {
-        return Err(Box::new(VCFParseError::InvalidVCFFile(
-            fpath.to_string_lossy().to_string(),
-        )));
+        return Err(Box::new(VCFParseError::InvalidVCFFile(source.to_string())));
     }
 
-    let file = File::open(fpath)?;
-    let mut file = MultiGzDecoder::new(file);
-    file.read_exact(&mut buffer)?;
+    let result = MultiGzDecoder::new(BufReader::new(&mut *reader)).read_exact(&mut buffer);
+    reader.seek(SeekFrom::Start(start))?;
+    result?;
     if buffer == [0x23, 0x23] {
         return Ok(VcfFileKind::GzippedVcf);
     }
     Err(Box::new(VCFParseError::InvalidGzipVCFFile(
-        fpath.to_string_lossy().to_string(),
+        source.to_string(),
+    )))
+}
+
+#[cfg(feature = "zstd")]
+fn sniff_zstd<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    source: &str,
+) -> Result<VcfFileKind, Box<dyn std::error::Error>> {
+    let mut magic = [0; 4];
+    let result = reader.read_exact(&mut magic);
+    reader.seek(SeekFrom::Start(start))?;
+    result?;
+    if magic != ZSTD_MAGIC {
+        return Err(Box::new(VCFParseError::InvalidVCFFile(source.to_string())));
+    }
+
+    let mut buffer = [0; 2];
+    let result = zstd::Decoder::new(&mut *reader)?.read_exact(&mut buffer);
+    reader.seek(SeekFrom::Start(start))?;
+    result?;
+    if buffer == [0x23, 0x23] {
+        return Ok(VcfFileKind::ZstdVcf);
+    }
+    Err(Box::new(VCFParseError::InvalidZstdVCFFile(
+        source.to_string(),
     )))
 }
 
-pub fn read_vcf_file(fpath: &PathBuf) -> Result<Variants, Box<dyn std::error::Error>> {
-    let kind = match guess_vcf_file_kind(fpath) {
-        Ok(kind) => kind,
-        Err(e) => return Err(e),
+pub fn guess_vcf_file_kind(fpath: &PathBuf) -> Result<VcfFileKind, Box<dyn std::error::Error>> {
+    let mut file = File::open(fpath)?;
+    sniff_kind(&mut file, &fpath.to_string_lossy())
+}
+
+/// Detects whether a reader holds a plain, gzipped or, with the `zstd`
+/// feature, zstd-compressed VCF from its first bytes, and returns it rewound to where it was.
+pub fn sniff_reader<R: Read + Seek>(
+    mut reader: R,
+) -> Result<(VcfFileKind, R), Box<dyn std::error::Error>> {
+    let kind = sniff_kind(&mut reader, "reader")?;
+    Ok((kind, reader))
+}
+
+/// Detects whether a stream that can not be rewound, like a download, holds a
+/// plain, gzipped or, with the `zstd` feature, zstd-compressed VCF from its
+/// magic bytes, and returns a reader that yields it from the start.
+///
+/// Unlike `sniff_reader`, the content of the compressed streams is not checked.
+pub fn sniff_stream<R: Read>(
+    mut reader: R,
+    source: &str,
+) -> Result<(VcfFileKind, impl Read), Box<dyn std::error::Error>> {
+    let mut magic = Vec::with_capacity(4);
+    // A single read can return fewer bytes than those of the magic.
+    (&mut reader).take(4).read_to_end(&mut magic)?;
+    let kind = match magic[..] {
+        [0x23, 0x23, ..] => VcfFileKind::PlainTextVcf,
+        [0x1f, 0x8b, ..] => VcfFileKind::GzippedVcf,
+        #[cfg(feature = "zstd")]
+        _ if magic == ZSTD_MAGIC => VcfFileKind::ZstdVcf,
+        _ => return Err(Box::new(VCFParseError::InvalidVCFFile(source.to_string()))),
     };
+    Ok((kind, Cursor::new(magic).chain(reader)))
+}
+
+fn open_vcf_buffer(
+    fpath: &PathBuf,
+    options: &VcfReaderBuilder,
+) -> Result<Box<dyn BufRead>, Box<dyn std::error::Error>> {
+    let kind = guess_vcf_file_kind(fpath)?;
 
     let file = File::open(fpath)?;
+    let capacity = options.buffer_capacity;
 
-    if kind == VcfFileKind::PlainTextVcf {
-        let file = BufReader::new(file);
-        match parse_vcf_buffer(file) {
-            Ok(vars) => return Ok(vars),
-            Err(e) => return Err(Box::new(e)),
+    match kind {
+        VcfFileKind::PlainTextVcf => Ok(Box::new(BufReader::with_capacity(capacity, file))),
+        VcfFileKind::GzippedVcf => {
+            let decoder = MultiGzDecoder::new(BufReader::with_capacity(capacity, file));
+            #[cfg(feature = "parallel")]
+            if options.threaded_decompression {
+                return Ok(Box::new(parallel::decompress_in_thread(decoder, capacity)));
+            }
+            Ok(Box::new(BufReader::with_capacity(capacity, decoder)))
         }
-    } else if kind == VcfFileKind::GzippedVcf {
-        let file = MultiGzDecoder::new(file);
-        let file = BufReader::new(file);
-        match parse_vcf_buffer(file) {
-            Ok(vars) => return Ok(vars),
-            Err(e) => return Err(Box::new(e)),
+        #[cfg(feature = "zstd")]
+        VcfFileKind::ZstdVcf => {
+            let decoder = zstd::Decoder::with_buffer(BufReader::with_capacity(capacity, file))?;
+            Ok(Box::new(BufReader::with_capacity(capacity, decoder)))
         }
     }
-    Err(Box::new(VCFParseError::InvalidVCFFile(
-        fpath.to_string_lossy().to_string(),
-    )))
+}
+
+/// Reader options, `read_vcf_file` uses the defaults.
+#[derive(Debug, Clone)]
+pub struct VcfReaderBuilder {
+    require_sorted: bool,
+    lenient_whitespace: bool,
+    buffer_capacity: usize,
+    cache_genotypes: bool,
+    allow_empty: bool,
+    ploidy: Option<u8>,
+    mixed_ploidy: bool,
+    half_call_policy: HalfCallPolicy,
+    broadcast_single_genotype: bool,
+    chrom_order: Option<ChromOrder>,
+    format_fields: Vec<String>,
+    #[cfg(feature = "parallel")]
+    threaded_decompression: bool,
+}
+
+impl Default for VcfReaderBuilder {
+    fn default() -> VcfReaderBuilder {
+        VcfReaderBuilder {
+            require_sorted: false,
+            lenient_whitespace: false,
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+            cache_genotypes: false,
+            allow_empty: false,
+            ploidy: None,
+            mixed_ploidy: false,
+            half_call_policy: HalfCallPolicy::Keep,
+            broadcast_single_genotype: false,
+            chrom_order: None,
+            format_fields: vec![GT_FIELD_ID.to_string()],
+            #[cfg(feature = "parallel")]
+            threaded_decompression: false,
+        }
+    }
+}
+
+impl VcfReaderBuilder {
+    pub fn new() -> VcfReaderBuilder {
+        VcfReaderBuilder::default()
+    }
+
+    /// Capacity in bytes of the read buffers, for the compressed and the
+    /// decompressed data of gzipped files.
+    pub fn buffer_capacity(mut self, buffer_capacity: usize) -> VcfReaderBuilder {
+        self.buffer_capacity = buffer_capacity;
+        self
+    }
+
+    /// Reuse the alleles of the last few distinct GT strings instead of parsing
+    /// them again, worth it when many samples share genotypes.
+    pub fn cache_genotypes(mut self, cache_genotypes: bool) -> VcfReaderBuilder {
+        self.cache_genotypes = cache_genotypes;
+        self
+    }
+
+    /// Decompress gzipped files in a dedicated thread, overlapping it with the
+    /// parsing.
+    #[cfg(feature = "parallel")]
+    pub fn threaded_decompression(mut self, threaded_decompression: bool) -> VcfReaderBuilder {
+        self.threaded_decompression = threaded_decompression;
+        self
+    }
+
+    /// Yield an `UnsortedInput` error if a position is lower than the previous
+    /// one of the same chromosome.
+    pub fn require_sorted(mut self, require_sorted: bool) -> VcfReaderBuilder {
+        self.require_sorted = require_sorted;
+        self
+    }
+
+    /// With `require_sorted`, also yield an `UnsortedChromosomes` error if a
+    /// chromosome is found after one that goes after it in this order.
+    pub fn chrom_order(mut self, chrom_order: ChromOrder) -> VcfReaderBuilder {
+        self.chrom_order = Some(chrom_order);
+        self
+    }
+
+    /// Accept files with a header but no variants, yielding no variants and
+    /// a ploidy of 0, instead of failing with `NoVariantsError`.
+    pub fn allow_empty(mut self, allow_empty: bool) -> VcfReaderBuilder {
+        self.allow_empty = allow_empty;
+        self
+    }
+
+    /// Expect this ploidy instead of taking it from the first called genotype
+    /// of the first variant. Genotypes of any other ploidy are a
+    /// `DifferentPloidiesError`.
+    pub fn ploidy(mut self, ploidy: u8) -> VcfReaderBuilder {
+        self.ploidy = Some(ploidy);
+        self
+    }
+
+    /// Accept genotypes of different ploidies, like those of the sex
+    /// chromosomes, instead of failing with `DifferentPloidiesError`. The
+    /// ploidy of the variants is then that of their first genotype.
+    pub fn mixed_ploidy(mut self, mixed_ploidy: bool) -> VcfReaderBuilder {
+        self.mixed_ploidy = mixed_ploidy;
+        self
+    }
+
+    /// How to read genotypes with some, but not all, alleles missing.
+    pub fn half_call_policy(mut self, half_call_policy: HalfCallPolicy) -> VcfReaderBuilder {
+        self.half_call_policy = half_call_policy;
+        self
+    }
+
+    /// Give the genotype of lines with a single genotype column to all the
+    /// samples, for simulated files that are not spec compliant.
+    pub fn broadcast_single_genotype(
+        mut self,
+        broadcast_single_genotype: bool,
+    ) -> VcfReaderBuilder {
+        self.broadcast_single_genotype = broadcast_single_genotype;
+        self
+    }
+
+    /// FORMAT subfields to keep for every sample, read with
+    /// `Variant::format_field`. GT is always parsed, and by default it is the
+    /// only one, so the rest are skipped.
+    pub fn format_fields(mut self, keys: &[&str]) -> VcfReaderBuilder {
+        self.format_fields = keys.iter().map(|key| key.to_string()).collect();
+        self
+    }
+
+    /// Split the variant lines on any run of whitespace instead of on tabs,
+    /// for hand-edited files. The header is always read tab-delimited.
+    pub fn lenient_whitespace(mut self, lenient_whitespace: bool) -> VcfReaderBuilder {
+        self.lenient_whitespace = lenient_whitespace;
+        self
+    }
+
+    pub fn read_file(
+        &self,
+        fpath: &PathBuf,
+    ) -> Result<Variants<'static>, Box<dyn std::error::Error>> {
+        let file = open_vcf_buffer(fpath, self)?;
+        Ok(parse_vcf_buffer(file, self)?)
+    }
+
+    /// Parses an already decompressed VCF.
+    pub fn read_buffer<'a, T: BufRead + 'a>(&self, file: T) -> Result<Variants<'a>, VCFParseError> {
+        parse_vcf_buffer(file, self)
+    }
+}
+
+pub fn read_vcf_file(fpath: &PathBuf) -> Result<Variants<'static>, Box<dyn std::error::Error>> {
+    VcfReaderBuilder::new().read_file(fpath)
+}
+
+/// Reads only the header of a plain or gzipped VCF file.
+pub fn read_vcf_header(fpath: &PathBuf) -> Result<VcfHeader, Box<dyn std::error::Error>> {
+    let mut file = open_vcf_buffer(fpath, &VcfReaderBuilder::default())?;
+    Ok(header::read_header(&mut file)?)
+}
+
+/// Counts the variant lines of a plain or gzipped VCF file without parsing them.
+pub fn count_variants(fpath: &PathBuf) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut file = open_vcf_buffer(fpath, &VcfReaderBuilder::default())?;
+    let mut line = Vec::new();
+    let mut n_vars = 0;
+    loop {
+        line.clear();
+        if file.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        if !line.starts_with(b"#") && !line.trim_ascii().is_empty() {
+            n_vars += 1;
+        }
+    }
+    Ok(n_vars)
+}
+
+/// The number of variant lines of a file and its variants, for progress bars.
+///
+/// The file is read twice, the first time without parsing the lines, so a
+/// gzipped file is also decompressed twice.
+pub fn read_vcf_with_count(
+    fpath: &PathBuf,
+) -> Result<(u64, Variants<'static>), Box<dyn std::error::Error>> {
+    let n_vars = count_variants(fpath)?;
+    Ok((n_vars, read_vcf_file(fpath)?))
+}
+
+const NUM_VARIANTS_TO_SAMPLE_FOR_SIZE: usize = 1000;
+
+/// Estimates the memory needed to collect all the variants of a file.
+///
+/// The mean size of the first variants is extrapolated to the number of
+/// variant lines, so the whole file is read once.
+pub fn estimate_collect_bytes(fpath: &PathBuf) -> Result<u64, Box<dyn std::error::Error>> {
+    let vars = read_vcf_file(fpath)?;
+    let mut n_sampled = 0;
+    let mut sampled_bytes = 0;
+    for var in vars.vars_iter.take(NUM_VARIANTS_TO_SAMPLE_FOR_SIZE) {
+        sampled_bytes += std::mem::size_of::<Variant>() + var?.heap_size();
+        n_sampled += 1;
+    }
+    if n_sampled == 0 {
+        return Ok(0);
+    }
+    let n_vars = count_variants(fpath)?;
+    Ok((sampled_bytes as f64 / n_sampled as f64 * n_vars as f64).round() as u64)
 }
 
 #[cfg(test)]
@@ -377,7 +1746,7 @@ mod tests {
     use self::super::*;
 
     // VCF example taken from https://samtools.github.io/hts-specs/VCFv4.5.pdf
-    const VCF_45: &str = "##fileformat=VCFv4.5
+    pub(crate) const VCF_45: &str = "##fileformat=VCFv4.5
 ##fileDate=20090805
 ##source=myImputationProgramV3.1
 ##reference=file:///seq/references/1000GenomesPilot-NCBI36.fasta
@@ -403,12 +1772,769 @@ mod tests {
 20\t1234567\tmicrosat1\tGTC\tG,GTCT\t50\tPASS\tNS=3;DP=9;AA=G\tGT:GQ:DP\t0/1:35:4\t0/2:17:2\t1/1:40:3
 20\t1234567\tmicrosat1\tGTC\tG,GTCT\t50\tPASS\tNS=3;DP=9;AA=G\tGT:GQ:DP\t0/1:35:4\t0/2:17:2\t1/1:40:3";
 
+    pub(crate) const VCF_MISSING: &str = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2\tS3
+20\t100\t.\tA\tG\t.\tPASS\t.\tGT\t0/1\t./.\t1/1
+20\t200\t.\tC\tT\t.\tPASS\t.\tGT\t0/.\t0/0\t1|0";
+
+    pub(crate) fn write_temp_vcf(name: &str, vcf: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("nei_rs_{name}.vcf"));
+        std::fs::write(&path, vcf).expect("Error writing temporary VCF");
+        path
+    }
+
+    pub(crate) fn parse_vcf_buffer_str(vcf: &str) -> Variants<'_> {
+        parse_vcf_buffer(BufReader::new(vcf.as_bytes()), &VcfReaderBuilder::new()).expect("Error")
+    }
+
+    pub(crate) fn parse_vcf_str(vcf: &str) -> Vec<Variant> {
+        parse_vcf_buffer_str(vcf)
+            .vars_iter
+            .map(|var| var.expect("Error reading variant"))
+            .collect()
+    }
+
     #[test]
     fn it_works() {
         let mock_file = BufReader::new(VCF_45.as_bytes());
-        let vars = parse_vcf_buffer(mock_file).expect("Error");
+        let vars = parse_vcf_buffer(mock_file, &VcfReaderBuilder::new()).expect("Error");
         for var_res in vars.vars_iter {
             let _var = var_res.expect("Error reading variant");
         }
     }
+
+    #[test]
+    fn parse_gts_with_missing_alleles() {
+        let vars = parse_vcf_str(VCF_MISSING);
+        assert_eq!(vars[0].gts, vec![vec![0, 1], vec![-1, -1], vec![1, 1]]);
+        assert_eq!(vars[1].gts, vec![vec![0, -1], vec![0, 0], vec![1, 0]]);
+        assert_eq!(vars[1].phased, vec![false, false, true]);
+    }
+
+    #[test]
+    fn allele_number_and_called_samples() {
+        let vars = parse_vcf_str(VCF_45);
+        assert_eq!(vars[0].allele_number(), 6);
+        assert_eq!(vars[0].called_samples(), 3);
+
+        let vars = parse_vcf_str(VCF_MISSING);
+        assert_eq!(vars[0].allele_number(), 4);
+        assert_eq!(vars[0].called_samples(), 2);
+        assert_eq!(vars[1].allele_number(), 5);
+        assert_eq!(vars[1].called_samples(), 2);
+    }
+
+    #[test]
+    fn reduce_stops_at_parse_error() {
+        let vcf =
+            VCF_MISSING.to_string() + "\n20\tnot_a_pos\t.\tA\tG\t.\tPASS\t.\tGT\t0/0\t0/0\t0/0";
+        let vars = parse_vcf_buffer_str(&vcf);
+        let result = reduce_variants(vars, 0, |n_vars, _var| n_vars + 1);
+        assert!(matches!(result, Err(VCFParseError::PosNotInt(_))));
+    }
+
+    #[test]
+    fn unsorted_input() {
+        let vcf = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1
+20\t200\t.\tA\tG\t.\tPASS\t.\tGT\t0/1
+21\t100\t.\tA\tG\t.\tPASS\t.\tGT\t0/1
+20\t150\t.\tA\tG\t.\tPASS\t.\tGT\t0/1";
+        let mut vars = parse_vcf_buffer_str(vcf);
+        assert!(vars.vars_iter.all(|var| var.is_ok()));
+
+        let mut vars = VcfReaderBuilder::new()
+            .require_sorted(true)
+            .read_buffer(vcf.as_bytes())
+            .unwrap();
+        assert!(vars.vars_iter.next().unwrap().is_ok());
+        assert!(vars.vars_iter.next().unwrap().is_ok());
+        match vars.vars_iter.next().unwrap() {
+            Err(VCFParseError::UnsortedInput { chrom, pos, prev }) => {
+                assert_eq!((chrom.as_str(), pos, prev), ("20", 150, 200))
+            }
+            _ => panic!("Unsorted input not detected"),
+        }
+    }
+
+    #[test]
+    fn unsorted_chromosomes() {
+        let vcf = VCF_MISSING
+            .replace("\n20\t100\t", "\nchr2\t100\t")
+            .replace("\n20\t200\t", "\nchr10\t200\t");
+        let read = |vcf: &str, order: ChromOrder| -> Vec<Result<Variant, VCFParseError>> {
+            VcfReaderBuilder::new()
+                .require_sorted(true)
+                .chrom_order(order)
+                .read_buffer(vcf.as_bytes())
+                .unwrap()
+                .vars_iter
+                .collect()
+        };
+        assert!(read(&vcf, ChromOrder::Natural)
+            .iter()
+            .all(|var| var.is_ok()));
+
+        let order = ChromOrder::List(vec!["chr10".to_string(), "chr2".to_string()]);
+        match &read(&vcf, order)[1] {
+            Err(VCFParseError::UnsortedChromosomes { chrom, prev }) => {
+                assert_eq!((chrom.as_str(), prev.as_str()), ("chr10", "chr2"))
+            }
+            _ => panic!("Unsorted chromosomes not detected"),
+        }
+    }
+
+    #[test]
+    fn variant_heap_size() {
+        let vars = parse_vcf_str(VCF_45);
+        let gts_size =
+            3 * std::mem::size_of::<Vec<AlleleInt>>() + 3 * 2 * std::mem::size_of::<AlleleInt>();
+        assert!(vars[0].heap_size() >= gts_size + 2 * std::mem::size_of::<String>());
+        assert!(vars[2].heap_size() > vars[0].heap_size());
+    }
+
+    #[test]
+    fn chrom_prefix() {
+        let vcf = VCF_MISSING.replace("\n20\t", "\nchr20\t");
+        let vars = parse_vcf_buffer_str(&vcf).strip_chr_prefix();
+        let chroms: Vec<String> = vars.vars_iter.map(|var| var.unwrap().chrom).collect();
+        assert_eq!(chroms, vec!["20", "20"]);
+
+        let vars = parse_vcf_buffer_str(&vcf).add_chr_prefix();
+        let chroms: Vec<String> = vars.vars_iter.map(|var| var.unwrap().chrom).collect();
+        assert_eq!(chroms, vec!["chr20", "chr20"]);
+
+        let vars = parse_vcf_buffer_str(VCF_MISSING).add_chr_prefix();
+        let chroms: Vec<String> = vars.vars_iter.map(|var| var.unwrap().chrom).collect();
+        assert_eq!(chroms, vec!["chr20", "chr20"]);
+    }
+
+    #[test]
+    fn take_and_skip() {
+        let vars = parse_vcf_buffer_str(VCF_45).skip(1).take(2);
+        assert_eq!(vars.samples.len(), 3);
+        let positions: Vec<u64> = vars.vars_iter.map(|var| var.unwrap().pos).collect();
+        assert_eq!(positions, vec![17330, 1110696]);
+    }
+
+    #[test]
+    fn peek_first() {
+        let mut vars = parse_vcf_buffer_str(VCF_45);
+        assert_eq!(vars.peek_first().unwrap().unwrap().pos, 14370);
+        assert_eq!(vars.peek_first().unwrap().unwrap().pos, 14370);
+        let positions: Vec<u64> = vars.vars_iter.map(|var| var.unwrap().pos).collect();
+        assert_eq!(positions.len(), 6);
+        assert_eq!(positions[0], 14370);
+    }
+
+    #[test]
+    fn gvcf_reference_blocks() {
+        let vcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1
+1\t100\t.\tA\t<NON_REF>\t.\t.\tEND=150\tGT\t0/0
+1\t151\t.\tCT\tC,<NON_REF>\t50\t.\tDP=10;DB\tGT\t0/1
+";
+        let vars = parse_vcf_str(vcf);
+        assert!(vars[0].is_reference_block());
+        assert_eq!(vars[0].end(), 150);
+        assert!(!vars[1].is_reference_block());
+        assert_eq!(vars[1].end(), 152);
+        assert_eq!(vars[1].span_end(), 152);
+        assert_eq!(vars[1].info("DP"), Some("10"));
+        assert_eq!(vars[1].info("DB"), Some(""));
+        assert_eq!(vars[1].info("END"), None);
+    }
+
+    #[test]
+    fn lenient_whitespace() {
+        let vcf = VCF_MISSING.replace("20\t200\t.\tC\tT", "20  200 .\tC T");
+        let mut vars = VcfReaderBuilder::new().read_buffer(vcf.as_bytes()).unwrap();
+        vars.vars_iter.next().unwrap().unwrap();
+        assert!(vars.vars_iter.next().unwrap().is_err());
+
+        let vars = VcfReaderBuilder::new()
+            .lenient_whitespace(true)
+            .read_buffer(vcf.as_bytes())
+            .unwrap();
+        let vars: Vec<Variant> = vars.vars_iter.map(|var| var.unwrap()).collect();
+        assert_eq!(vars[1].pos, 200);
+        assert_eq!(vars[1].alleles, vec!["C", "T"]);
+        assert_eq!(vars[1].gts, vec![vec![0, -1], vec![0, 0], vec![1, 0]]);
+    }
+
+    #[test]
+    fn effective_num_alleles() {
+        let vars = parse_vcf_str(VCF_45);
+        assert!((vars[0].effective_num_alleles() - 2.0).abs() < 1e-12);
+        assert!((vars[2].effective_num_alleles() - 1.8).abs() < 1e-12);
+        assert_eq!(vars[3].effective_num_alleles(), 1.0);
+
+        let vars = parse_vcf_str(&VCF_MISSING.replace("0/1\t./.\t1/1", "./.\t./.\t./."));
+        assert!(vars[0].effective_num_alleles().is_nan());
+    }
+
+    #[test]
+    fn genotype_counts() {
+        let vars = parse_vcf_str(VCF_45);
+        let counts: Vec<(u64, u64, u64)> = vars
+            .iter()
+            .map(|var| (var.het_count(), var.hom_ref_count(), var.hom_alt_count()))
+            .collect();
+        assert_eq!(counts[..4], [(1, 1, 1), (1, 2, 0), (2, 0, 0), (0, 3, 0)]);
+
+        let vars = parse_vcf_str(VCF_MISSING);
+        assert_eq!(vars[1].het_count(), 1);
+        assert_eq!(vars[1].hom_ref_count(), 1);
+    }
+
+    #[test]
+    fn shannon_diversity() {
+        let vars = parse_vcf_str(VCF_45);
+        assert!((vars[0].shannon_diversity() - 2.0_f64.ln()).abs() < 1e-12);
+        let expected = -(1.0 / 3.0 * (1.0_f64 / 3.0).ln() + 2.0 / 3.0 * (2.0_f64 / 3.0).ln());
+        assert!((vars[2].shannon_diversity() - expected).abs() < 1e-12);
+        assert_eq!(vars[3].shannon_diversity(), 0.0);
+
+        let vars = parse_vcf_str(&VCF_MISSING.replace("0/1\t./.\t1/1", "./.\t./.\t./."));
+        assert!(vars[0].shannon_diversity().is_nan());
+    }
+
+    #[test]
+    fn ref_and_alt_alleles() {
+        let mut var = parse_vcf_str(VCF_MISSING).remove(0);
+        assert_eq!(
+            (var.ref_allele(), var.alt_alleles()),
+            ("A", &["G".to_string()][..])
+        );
+        var.alleles.clear();
+        assert_eq!(var.ref_allele(), "");
+        assert!(var.alt_alleles().is_empty());
+    }
+
+    #[test]
+    fn same_site() {
+        let vars = parse_vcf_str(VCF_45);
+        // Same site, with the ALTs in another order.
+        let reordered = VCF_45.replace("\tA\tG,T\t", "\tA\tT,G\t");
+        let reordered = parse_vcf_str(&reordered);
+        assert!(vars[2].same_site(&reordered[2], true));
+        assert!(vars[4].same_site(&vars[5], true));
+
+        let other_alts = VCF_45.replace("\tA\tG,T\t", "\tA\tC,G\t");
+        let other_alts = parse_vcf_str(&other_alts);
+        assert!(!vars[2].same_site(&other_alts[2], true));
+        assert!(vars[2].same_site(&other_alts[2], false));
+
+        assert!(!vars[0].same_site(&vars[1], false));
+        let other_ref = VCF_45.replace("\tG\tA\t", "\tC\tA\t");
+        assert!(!vars[0].same_site(&parse_vcf_str(&other_ref)[0], false));
+    }
+
+    #[test]
+    fn filter_status() {
+        let vcf = VCF_MISSING.replace("\t200\t.\tC\tT\t.\tPASS\t", "\t200\t.\tC\tT\t.\t.\t");
+        let vars = parse_vcf_str(&vcf);
+        assert_eq!(vars[0].filter_status(), &FilterStatus::Pass);
+        assert_eq!(vars[1].filter_status(), &FilterStatus::Missing);
+        assert!(vars[1].to_vcf_line("GT").contains("\t.\t.\tGT\t"));
+
+        let vcf = VCF_MISSING.replace("\tPASS\t", "\tq10;s50\t");
+        let var = &parse_vcf_str(&vcf)[0];
+        let failed = vec!["q10".to_string(), "s50".to_string()];
+        assert_eq!(var.filter_status(), &FilterStatus::Failed(failed));
+        assert!(var.to_vcf_line("GT").contains("\tq10;s50\t"));
+    }
+
+    #[test]
+    fn minor_allele_count() {
+        let vars = parse_vcf_str(VCF_45);
+        assert_eq!(vars[0].minor_allele_count(), 3);
+        assert_eq!(vars[1].minor_allele_count(), 1);
+        assert_eq!(vars[3].minor_allele_count(), 0);
+
+        let vars = parse_vcf_buffer_str(VCF_45).filter_mac(2);
+        let positions: Vec<u64> = vars.vars_iter.map(|var| var.unwrap().pos).collect();
+        assert_eq!(positions, vec![14370, 1110696, 1234567, 1234567]);
+    }
+
+    #[test]
+    fn allele_freq_table() {
+        let vars = parse_vcf_str(VCF_45);
+        let table = vars[2].allele_freq_table();
+        let alleles: Vec<&str> = table.iter().map(|row| row.allele.as_str()).collect();
+        assert_eq!(alleles, vec!["A", "G", "T"]);
+        assert_eq!(table[2].count, 4);
+        assert!((table[1].freq - 1.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn missing_alt() {
+        let vars = parse_vcf_str(VCF_45);
+        assert_eq!(vars[3].pos, 1230237);
+        assert_eq!(vars[3].alleles, vec!["T"]);
+        assert!(vars[3].alt_alleles().is_empty());
+        assert_eq!(vars[3].allele_counts(), vec![6]);
+        assert_eq!(vars[3].allele_frequencies(), vec![1.0]);
+
+        let vcf = VCF_MISSING
+            .replace("A\tG", "A\t.,*")
+            .replace("0/1\t./.\t1/1", "0/2\t./.\t0/0");
+        let vars = parse_vcf_str(&vcf);
+        assert_eq!(vars[0].alleles, vec!["A", ".", "*"]);
+    }
+
+    #[test]
+    fn cached_genotypes() {
+        for vcf in [VCF_45, VCF_MISSING] {
+            let cached = VcfReaderBuilder::new()
+                .cache_genotypes(true)
+                .read_buffer(vcf.as_bytes())
+                .unwrap();
+            let cached: Vec<Variant> = cached.vars_iter.map(|var| var.unwrap()).collect();
+            for (cached, var) in cached.iter().zip(parse_vcf_str(vcf)) {
+                assert_eq!(cached.gts, var.gts);
+                assert_eq!(cached.phased, var.phased);
+            }
+        }
+
+        let vcf = VCF_MISSING.replace("1|0", "1/0/1");
+        let vars = VcfReaderBuilder::new()
+            .cache_genotypes(true)
+            .read_buffer(vcf.as_bytes())
+            .unwrap();
+        assert!(vars.vars_iter.last().unwrap().is_err());
+    }
+
+    #[test]
+    fn empty_file_and_no_variants() {
+        let result = VcfReaderBuilder::new().read_buffer("".as_bytes());
+        assert!(matches!(result, Err(VCFParseError::EmptyFile)));
+
+        let header_only = VCF_MISSING.split("\n20\t").next().unwrap();
+        let result = VcfReaderBuilder::new().read_buffer(header_only.as_bytes());
+        assert!(matches!(result, Err(VCFParseError::NoVariantsError)));
+        let mut vars = VcfReaderBuilder::new()
+            .allow_empty(true)
+            .read_buffer(header_only.as_bytes())
+            .unwrap();
+        assert_eq!(vars.samples, vec!["S1", "S2", "S3"]);
+        assert_eq!(vars.ploidy, 0);
+        assert!(vars.vars_iter.next().is_none());
+        let result = VcfReaderBuilder::new()
+            .allow_empty(true)
+            .read_buffer("".as_bytes());
+        assert!(matches!(result, Err(VCFParseError::EmptyFile)));
+
+        let bad_first_line = VCF_MISSING.replace("\t100\t", "\tnot_a_pos\t");
+        let result = VcfReaderBuilder::new().read_buffer(bad_first_line.as_bytes());
+        assert!(matches!(result, Err(VCFParseError::PosNotInt(_))));
+    }
+
+    #[test]
+    fn polarize_from_aa() {
+        let vars: Vec<Variant> = parse_vcf_buffer_str(VCF_45)
+            .polarize_from_aa()
+            .vars_iter
+            .map(|var| var.unwrap())
+            .collect();
+        assert_eq!(
+            vars.iter().map(|var| var.pos).collect::<Vec<u64>>(),
+            vec![1110696, 1230237, 1234567, 1234567]
+        );
+        assert_eq!(vars[0].alleles, vec!["T", "G", "A"]);
+        assert_eq!(vars[0].gts, vec![vec![1, 0], vec![0, 1], vec![0, 0]]);
+        assert_eq!(vars[1].alleles, vec!["T"]);
+        assert_eq!(vars[2].alleles, vec!["G", "GTC", "GTCT"]);
+        assert_eq!(vars[2].gts, vec![vec![1, 0], vec![1, 2], vec![0, 0]]);
+
+        let mut var = parse_vcf_str(VCF_45).remove(0);
+        assert!(!var.polarize("C"));
+        assert_eq!(var.alleles, vec!["G", "A"]);
+        assert!(var.polarize("a"));
+        assert_eq!(var.gts, vec![vec![1, 1], vec![0, 1], vec![0, 0]]);
+    }
+
+    #[test]
+    fn missing_gt_subfield() {
+        let vcf = VCF_MISSING.replace("GT\t0/.\t0/0\t1|0", "GQ:GT\t30:0/1\t30\t30:1|0");
+        let mut vars = parse_vcf_buffer_str(&vcf);
+        match vars.vars_iter.nth(1).unwrap() {
+            Err(VCFParseError::GtFieldMissingForSample { sample_idx, .. }) => {
+                assert_eq!(sample_idx, 1)
+            }
+            result => panic!("Unexpected result: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn invalid_gt_characters() {
+        for gt in ["0<1", "0/>", ">/1", "0/1a"] {
+            let vcf = VCF_MISSING.replace("\t1|0", &format!("\t{gt}"));
+            let mut vars = parse_vcf_buffer_str(&vcf);
+            let result = vars.vars_iter.nth(1).unwrap();
+            assert!(
+                matches!(result, Err(VCFParseError::IncorrectAllele(_, _))),
+                "{gt}"
+            );
+        }
+    }
+
+    #[test]
+    fn allele_index_overflow() {
+        let too_large = AlleleInt::MAX as u64 + 1;
+        for allele in [too_large.to_string(), "999999".to_string(), "9".repeat(40)] {
+            let vcf = VCF_MISSING.replace("\t1|0", &format!("\t1|{allele}"));
+            let result = parse_vcf_buffer_str(&vcf).vars_iter.nth(1).unwrap();
+            assert!(
+                matches!(result, Err(VCFParseError::AlleleIndexTooLarge(_))),
+                "{allele}"
+            );
+        }
+    }
+
+    #[test]
+    fn allele_index_out_of_range() {
+        let vcf = VCF_MISSING.replace("\t1|0", "\t0/3");
+        let result = parse_vcf_buffer_str(&vcf).vars_iter.nth(1).unwrap();
+        assert!(matches!(
+            result,
+            Err(VCFParseError::AlleleIndexOutOfRange {
+                index: 3,
+                n_alleles: 2,
+                ..
+            })
+        ));
+        let vcf = VCF_MISSING.replace("\t1|0", "\t2|.");
+        let result = parse_vcf_buffer_str(&vcf).vars_iter.nth(1).unwrap();
+        assert!(matches!(
+            result,
+            Err(VCFParseError::AlleleIndexOutOfRange { index: 2, .. })
+        ));
+
+        // Without ALT only REF can be called.
+        let vcf = VCF_MISSING.replace("\tC\tT\t", "\tC\t.\t");
+        let result = parse_vcf_buffer_str(&vcf).vars_iter.nth(1).unwrap();
+        assert!(matches!(
+            result,
+            Err(VCFParseError::AlleleIndexOutOfRange {
+                index: 1,
+                n_alleles: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn vcf_lines() {
+        let vars = parse_vcf_str(VCF_45);
+        assert_eq!(
+            vars[0].to_vcf_line("GT"),
+            "20\t14370\trs6054257\tG\tA\t29\tPASS\tNS=3;DP=14;AF=0.5;DB;H2\tGT\t0|0\t1|0\t1/1"
+        );
+        assert_eq!(
+            vars[1].to_vcf_line("GT:DP"),
+            "20\t17330\t.\tT\tA\t3\tq10\tNS=3;DP=11;AF=0.017\tGT:DP\t0|0:.\t0|1:.\t0/0:."
+        );
+
+        let header: Vec<&str> = VCF_45
+            .lines()
+            .take_while(|line| line.starts_with('#'))
+            .collect();
+        let lines: Vec<String> = vars.iter().map(|var| var.to_vcf_line("GT")).collect();
+        let vcf = format!("{}\n{}", header.join("\n"), lines.join("\n"));
+        for (var, reparsed) in vars.iter().zip(parse_vcf_str(&vcf)) {
+            assert_eq!(
+                (&var.chrom, var.pos, &var.id, &var.alleles, var.qual),
+                (
+                    &reparsed.chrom,
+                    reparsed.pos,
+                    &reparsed.id,
+                    &reparsed.alleles,
+                    reparsed.qual
+                )
+            );
+            assert_eq!((&var.filter, &var.info), (&reparsed.filter, &reparsed.info));
+            assert_eq!((&var.gts, &var.phased), (&reparsed.gts, &reparsed.phased));
+        }
+
+        let var = &parse_vcf_str(VCF_MISSING)[0];
+        assert!(var
+            .to_vcf_line("GT")
+            .ends_with("\t.\tPASS\t.\tGT\t0/1\t./.\t1/1"));
+    }
+
+    #[test]
+    fn thin() {
+        let positions = |min_distance| -> Vec<u64> {
+            parse_vcf_buffer_str(&VCF_45.replace("\n20\t1234567\t", "\n21\t1234567\t"))
+                .thin(min_distance)
+                .vars_iter
+                .map(|var| var.unwrap().pos)
+                .collect()
+        };
+        assert_eq!(positions(0).len(), 6);
+        assert_eq!(positions(1).len(), 5);
+        assert_eq!(positions(3000), vec![14370, 1110696, 1230237, 1234567]);
+        assert_eq!(positions(1_000_000), vec![14370, 1110696, 1234567]);
+        assert_eq!(positions(u64::MAX), vec![14370, 1234567]);
+    }
+
+    #[test]
+    fn reservoir_sampling() {
+        let vcf: String = std::iter::once(
+            "##fileformat=VCFv4.5\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1"
+                .to_string(),
+        )
+        .chain((1..=1000).map(|pos| format!("1\t{pos}\t.\tA\tG\t.\tPASS\t.\tGT\t0/1")))
+        .collect::<Vec<String>>()
+        .join("\n");
+        let positions = |k, seed| -> Vec<u64> {
+            sample_variants(parse_vcf_buffer_str(&vcf), k, seed)
+                .unwrap()
+                .iter()
+                .map(|var| var.pos)
+                .collect()
+        };
+        let sample = positions(10, 42);
+        assert_eq!(sample.len(), 10);
+        assert!(sample.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(sample, positions(10, 42));
+        assert_ne!(sample, positions(10, 43));
+        // Not just the first ones.
+        assert!(sample.iter().any(|pos| *pos > 10));
+
+        let all = sample_variants(parse_vcf_buffer_str(VCF_45), 10, 1).unwrap();
+        assert_eq!(all.len(), 6);
+        assert!(sample_variants(parse_vcf_buffer_str(VCF_45), 0, 1)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn site_identity() {
+        let vars = parse_vcf_str(VCF_45);
+        // The duplicated microsatellite, with different genotypes and ALT order.
+        let mut other = parse_vcf_str(&VCF_45.replacen(
+            "\tG,GTCT\t50\tPASS\tNS=3;DP=9;AA=G\tGT:GQ:DP\t0/1:35:4",
+            "\tGTCT,G\t50\tPASS\tNS=3;DP=9;AA=G\tGT:GQ:DP\t1/1:35:4",
+            1,
+        ));
+        let microsat = other.remove(4);
+        assert_eq!(microsat.gts[0], vec![1, 1]);
+        assert_eq!(microsat, vars[4]);
+        assert!(!microsat.same_record(&vars[4]));
+        assert!(vars[4].same_record(&vars[5]));
+        assert_ne!(vars[0], vars[1]);
+
+        let sites: std::collections::HashSet<Variant> =
+            vars.into_iter().chain([microsat]).collect();
+        assert_eq!(sites.len(), 5);
+    }
+
+    #[test]
+    fn dedup_identical() {
+        let positions = |vcf: &str| -> Vec<u64> {
+            parse_vcf_buffer_str(vcf)
+                .dedup_identical()
+                .vars_iter
+                .map(|var| var.unwrap().pos)
+                .collect()
+        };
+        assert_eq!(
+            positions(VCF_45),
+            vec![14370, 17330, 1110696, 1230237, 1234567]
+        );
+        // Same site, different genotypes
+        let vcf = VCF_45.replacen("1/1:40:3", "1/2:40:3", 1);
+        assert_eq!(positions(&vcf).len(), 6);
+    }
+
+    #[test]
+    fn keep_biallelic() {
+        let (vars, n_dropped) = parse_vcf_buffer_str(VCF_45).keep_biallelic();
+        let positions: Vec<u64> = vars.vars_iter.map(|var| var.unwrap().pos).collect();
+        assert_eq!(positions, vec![14370, 17330]);
+        assert_eq!(n_dropped.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn half_calls() {
+        for (policy, expected) in [
+            (HalfCallPolicy::Keep, vec![0, -1]),
+            (HalfCallPolicy::Missing, vec![-1, -1]),
+            (HalfCallPolicy::Reference, vec![0, 0]),
+        ] {
+            let vars: Vec<Variant> = VcfReaderBuilder::new()
+                .half_call_policy(policy)
+                .read_buffer(VCF_MISSING.as_bytes())
+                .unwrap()
+                .vars_iter
+                .map(|var| var.unwrap())
+                .collect();
+            assert_eq!(vars[1].gts[0], expected, "{policy:?}");
+            assert_eq!(vars[0].gts[1], vec![-1, -1], "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn broadcast_single_genotype() {
+        let vcf = VCF_MISSING
+            .replace("\t0/1\t./.\t1/1", "\t0/1")
+            .replace("\t0/.\t0/0\t1|0", "\t1|0");
+        assert!(matches!(
+            VcfReaderBuilder::new().read_buffer(vcf.as_bytes()),
+            Err(VCFParseError::WrongNumberOfGenotypes {
+                expected: 3,
+                found: 1,
+                ..
+            })
+        ));
+
+        let vars: Vec<Variant> = VcfReaderBuilder::new()
+            .broadcast_single_genotype(true)
+            .read_buffer(vcf.as_bytes())
+            .unwrap()
+            .vars_iter
+            .map(|var| var.unwrap())
+            .collect();
+        assert_eq!(vars[0].gts, vec![vec![0, 1]; 3]);
+        assert_eq!(vars[1].gts, vec![vec![1, 0]; 3]);
+        assert!((0..3).all(|sample_idx| vars[1].is_phased(sample_idx)));
+    }
+
+    #[test]
+    fn format_fields() {
+        let vars = parse_vcf_str(VCF_45);
+        assert_eq!(vars[0].format_field("DP"), None);
+
+        let vars: Vec<Variant> = VcfReaderBuilder::new()
+            .format_fields(&["GT", "DP", "HQ", "XX"])
+            .read_buffer(VCF_45.as_bytes())
+            .unwrap()
+            .vars_iter
+            .map(|var| var.unwrap())
+            .collect();
+        assert_eq!(vars[0].format_field("DP").unwrap(), ["1", "8", "5"]);
+        assert_eq!(vars[1].format_field("HQ").unwrap(), ["58,50", "65,3", "."]);
+        assert_eq!(vars[0].format_field("GQ"), None);
+        assert_eq!(vars[0].format_field("GT"), None);
+        assert_eq!(vars[0].format_field("XX"), None);
+        // The microsatellite lines have no HQ.
+        assert_eq!(vars[4].format_field("HQ"), None);
+        assert_eq!(vars[4].format_field("DP").unwrap(), ["4", "2", "3"]);
+        assert!(vars[0]
+            .to_vcf_line("GT:DP:GQ")
+            .ends_with("\t0|0:1:.\t1|0:8:.\t1/1:5:."));
+    }
+
+    #[test]
+    fn sites_only() {
+        let vcf = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+20\t100\trs1\tA\tG\t.\tPASS\tAF=0.1
+20\t200\trs2\tC\tT,G\t30\tq10\t.";
+        let vars = parse_vcf_buffer_str(vcf);
+        assert!(vars.samples.is_empty());
+        assert_eq!((vars.ploidy, vars.ploidy_confident), (0, true));
+        let vars: Vec<Variant> = vars.vars_iter.map(|var| var.unwrap()).collect();
+        assert_eq!(vars.len(), 2);
+        assert_eq!(vars[0].info("AF"), Some("0.1"));
+        assert_eq!(vars[1].alt_alleles().len(), 2);
+        assert!(vars.iter().all(|var| var.gts.is_empty()));
+
+        let vcf = VCF_MISSING.replace("\t.\tGT\t0/1\t./.\t1/1", "\t.");
+        assert!(matches!(
+            VcfReaderBuilder::new().read_buffer(vcf.as_bytes()),
+            Err(VCFParseError::WrongNumberOfGenotypes { found: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn phase_sets() {
+        let vcf = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT:PS\t0|1:10\t0/1:.
+1\t20\t.\tC\tT\t.\tPASS\t.\tGT:DP:PS\t1|0:5:10\t1|0:3
+1\t30\t.\tG\tA\t.\tPASS\t.\tGT\t0|1\t0|1";
+        let vars = parse_vcf_str(vcf);
+        assert_eq!(vars[0].phase_set(0), Some(10));
+        assert_eq!(vars[0].phase_set(1), None);
+        assert_eq!(vars[1].phase_set(0), Some(10));
+        // The trailing PS was dropped.
+        assert_eq!(vars[1].phase_set(1), None);
+        assert_eq!(vars[2].phase_set(0), None);
+
+        let vcf = vcf.replace(":10\t0/1:.", ":ten\t0/1:.");
+        assert!(matches!(
+            VcfReaderBuilder::new().read_buffer(vcf.as_bytes()),
+            Err(VCFParseError::PhaseSetNotInt(..))
+        ));
+    }
+
+    #[test]
+    fn ragged_genotype_lines() {
+        let vcf = VCF_MISSING.replace("\t0/0\t1|0", "\t0/0");
+        let results: Vec<Result<Variant, VCFParseError>> =
+            parse_vcf_buffer_str(&vcf).vars_iter.collect();
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(VCFParseError::WrongNumberOfGenotypes {
+                expected: 3,
+                found: 2,
+                ..
+            })
+        ));
+
+        // Only the FORMAT column, in the line that defines the ploidy.
+        let vcf = VCF_MISSING.replace("\tGT\t0/1\t./.\t1/1", "\tGT");
+        assert!(matches!(
+            VcfReaderBuilder::new().read_buffer(vcf.as_bytes()),
+            Err(VCFParseError::WrongNumberOfGenotypes { found: 0, .. })
+        ));
+
+        // Truncated before the INFO column.
+        let vcf = VCF_MISSING.replace("\tPASS\t.\tGT\t0/.\t0/0\t1|0", "\tPASS");
+        let results: Vec<Result<Variant, VCFParseError>> =
+            parse_vcf_buffer_str(&vcf).vars_iter.collect();
+        assert!(matches!(
+            results[1],
+            Err(VCFParseError::TooFewColumns { found: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn ploidy_from_called_gt() {
+        let vcf = VCF_MISSING.replace("GT\t0/1\t./.\t1/1", "GT\t.\t.\t1/1/1");
+        let vars = parse_vcf_buffer_str(&vcf);
+        assert_eq!((vars.ploidy, vars.ploidy_confident), (3, true));
+
+        let vcf = VCF_MISSING.replace("GT\t0/1\t./.\t1/1", "GT\t./.\t./.\t.");
+        let vars = parse_vcf_buffer_str(&vcf);
+        assert_eq!((vars.ploidy, vars.ploidy_confident), (2, false));
+    }
+
+    #[test]
+    fn ploidy_override() {
+        let vcf = VCF_MISSING.replace("GT\t0/1\t./.", "GT\t.\t./.");
+        let result = VcfReaderBuilder::new().read_buffer(vcf.as_bytes());
+        assert_eq!(result.unwrap().ploidy, 2);
+
+        let vars: Vec<Variant> = VcfReaderBuilder::new()
+            .ploidy(2)
+            .read_buffer(vcf.as_bytes())
+            .unwrap()
+            .vars_iter
+            .map(|var| var.unwrap())
+            .collect();
+        assert_eq!(vars[0].gts, vec![vec![-1, -1], vec![-1, -1], vec![1, 1]]);
+
+        let vars = VcfReaderBuilder::new()
+            .ploidy(3)
+            .read_buffer(VCF_MISSING.as_bytes());
+        assert!(matches!(
+            vars,
+            Err(VCFParseError::DifferentPloidiesError(_))
+        ));
+    }
 }
@@ -0,0 +1,329 @@
+//! Genetic distances between populations, mostly Nei's.
+//!
+//! With `x_i` and `y_i` the frequencies of allele `i` in the populations,
+//! every locus contributes the gene identities `Jx = sum(x_i^2)`,
+//! `Jy = sum(y_i^2)` and `Jxy = sum(x_i * y_i)`, averaged over the loci in
+//! which both populations have a called allele.
+
+use crate::stats::{population_allele_counts, population_allele_freqs};
+use crate::{VCFParseError, Variant, Variants};
+use ndarray::Array2;
+
+/// Gene identities of a locus, see the module docs.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GeneIdentities {
+    pub(crate) jx: f64,
+    pub(crate) jy: f64,
+    pub(crate) jxy: f64,
+    /// `Jx` and `Jy` corrected for sample size, `(2n J - 1) / (2n - 1)`,
+    /// where `2n` is the number of called alleles.
+    pub(crate) jx_unbiased: f64,
+    pub(crate) jy_unbiased: f64,
+}
+
+impl GeneIdentities {
+    fn add(&mut self, other: &GeneIdentities) {
+        self.jx += other.jx;
+        self.jy += other.jy;
+        self.jxy += other.jxy;
+        self.jx_unbiased += other.jx_unbiased;
+        self.jy_unbiased += other.jy_unbiased;
+    }
+
+    fn scale(&mut self, factor: f64) {
+        self.jx *= factor;
+        self.jy *= factor;
+        self.jxy *= factor;
+        self.jx_unbiased *= factor;
+        self.jy_unbiased *= factor;
+    }
+}
+
+fn unbiased_identity(identity: f64, n_alleles: u64) -> f64 {
+    let n_alleles = n_alleles as f64;
+    (n_alleles * identity - 1.0) / (n_alleles - 1.0)
+}
+
+/// None if any population has no called allele.
+fn identities_from_counts(counts_a: &[u64], counts_b: &[u64]) -> Option<GeneIdentities> {
+    let n_a: u64 = counts_a.iter().sum();
+    let n_b: u64 = counts_b.iter().sum();
+    if n_a == 0 || n_b == 0 {
+        return None;
+    }
+
+    let mut identities = GeneIdentities::default();
+    for idx in 0..counts_a.len().max(counts_b.len()) {
+        let x = counts_a.get(idx).copied().unwrap_or(0) as f64 / n_a as f64;
+        let y = counts_b.get(idx).copied().unwrap_or(0) as f64 / n_b as f64;
+        identities.jx += x * x;
+        identities.jy += y * y;
+        identities.jxy += x * y;
+    }
+    identities.jx_unbiased = unbiased_identity(identities.jx, n_a);
+    identities.jy_unbiased = unbiased_identity(identities.jy, n_b);
+    Some(identities)
+}
+
+/// None if any population has no called allele.
+pub(crate) fn gene_identities(
+    var: &Variant,
+    pop_a: &[usize],
+    pop_b: &[usize],
+) -> Option<GeneIdentities> {
+    identities_from_counts(
+        &population_allele_counts(var, pop_a),
+        &population_allele_counts(var, pop_b),
+    )
+}
+
+/// Gene identities averaged over the loci.
+fn mean_gene_identities(
+    vars: &mut Variants,
+    pop_a: &[usize],
+    pop_b: &[usize],
+) -> Result<GeneIdentities, VCFParseError> {
+    let mut identities = GeneIdentities::default();
+    let mut n_loci = 0;
+    for var in vars.vars_iter.by_ref() {
+        if let Some(locus_identities) = gene_identities(&var?, pop_a, pop_b) {
+            identities.add(&locus_identities);
+            n_loci += 1;
+        }
+    }
+    identities.scale(1.0 / n_loci as f64);
+    Ok(identities)
+}
+
+/// Distances computed from the gene identities averaged over the loci.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// Nei's (1972) standard distance, `D = -ln(Jxy / sqrt(Jx * Jy))`.
+    NeiStandard,
+    /// Nei's (1973) minimum distance, `Dm = (Jx + Jy) / 2 - Jxy`.
+    NeiMinimum,
+    /// Reynolds et al. (1983) coancestry distance, `-ln(1 - theta)`, with
+    /// `theta = (Jx + Jy - 2 Jxy) / (2 (1 - Jxy))`, without sample size
+    /// correction.
+    Reynolds,
+    /// Mean number of differences per site between two alleles taken one
+    /// from each population, `Dxy = 1 - Jxy`.
+    Dxy,
+}
+
+impl DistanceMetric {
+    fn distance(&self, j: &GeneIdentities) -> f64 {
+        match self {
+            DistanceMetric::NeiStandard => -(j.jxy / (j.jx * j.jy).sqrt()).ln(),
+            DistanceMetric::NeiMinimum => (j.jx + j.jy) / 2.0 - j.jxy,
+            DistanceMetric::Reynolds => {
+                let theta = (j.jx + j.jy - 2.0 * j.jxy) / (2.0 * (1.0 - j.jxy));
+                -(1.0 - theta).ln()
+            }
+            DistanceMetric::Dxy => 1.0 - j.jxy,
+        }
+    }
+}
+
+/// Nei's (1972) standard genetic distance, `D = -ln(Jxy / sqrt(Jx * Jy))`.
+///
+/// NaN if no locus has called alleles in both populations.
+pub fn nei_standard_distance(
+    vars: &mut Variants,
+    pop_a: &[usize],
+    pop_b: &[usize],
+) -> Result<f64, VCFParseError> {
+    let j = mean_gene_identities(vars, pop_a, pop_b)?;
+    Ok(DistanceMetric::NeiStandard.distance(&j))
+}
+
+/// Nei's (1973) minimum genetic distance, `Dm = (Jx + Jy) / 2 - Jxy`.
+///
+/// NaN if no locus has called alleles in both populations.
+pub fn nei_minimum_distance(
+    vars: &mut Variants,
+    pop_a: &[usize],
+    pop_b: &[usize],
+) -> Result<f64, VCFParseError> {
+    let j = mean_gene_identities(vars, pop_a, pop_b)?;
+    Ok(DistanceMetric::NeiMinimum.distance(&j))
+}
+
+/// Nei's (1978) unbiased genetic distance, `D = -ln(Jxy / sqrt(Jx' * Jy'))`,
+/// with the per-locus identities corrected for sample size,
+/// `Jx' = (2n Jx - 1) / (2n - 1)`, `2n` being the number of called alleles.
+///
+/// NaN if no locus has called alleles in both populations.
+pub fn nei_unbiased_distance(
+    vars: &mut Variants,
+    pop_a: &[usize],
+    pop_b: &[usize],
+) -> Result<f64, VCFParseError> {
+    let j = mean_gene_identities(vars, pop_a, pop_b)?;
+    Ok(-(j.jxy / (j.jx_unbiased * j.jy_unbiased).sqrt()).ln())
+}
+
+/// Cavalli-Sforza and Edwards (1967) chord distance,
+/// `(2 / pi) sqrt(2 (1 - sum(sqrt(x_i y_i))))`, averaged over the loci in which
+/// both populations have a called allele.
+///
+/// NaN if there are no such loci.
+pub fn chord_distance(
+    vars: &mut Variants,
+    pop_a: &[usize],
+    pop_b: &[usize],
+) -> Result<f64, VCFParseError> {
+    let mut distance = 0.0;
+    let mut n_loci = 0;
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        let freqs_a = population_allele_freqs(&var, pop_a);
+        let freqs_b = population_allele_freqs(&var, pop_b);
+        if freqs_a.is_empty() || freqs_b.is_empty() {
+            continue;
+        }
+        let sum_sqrt: f64 = freqs_a
+            .iter()
+            .zip(&freqs_b)
+            .map(|(x, y)| (x * y).sqrt())
+            .sum();
+        // Rounding can take the sum of identical populations slightly over 1.
+        distance += 2.0 / std::f64::consts::PI * (2.0 * (1.0 - sum_sqrt).max(0.0)).sqrt();
+        n_loci += 1;
+    }
+    Ok(distance / n_loci as f64)
+}
+
+/// Symmetric `(n_pops, n_pops)` matrix with the distances between every pair
+/// of populations, computed in a single pass over the variants.
+///
+/// The diagonal is 0. Every pair is averaged over the loci in which both
+/// populations have a called allele, and it is NaN if there are none.
+pub fn population_distance_matrix(
+    vars: &mut Variants,
+    pops: &[Vec<usize>],
+    metric: DistanceMetric,
+) -> Result<Array2<f64>, VCFParseError> {
+    let n_pops = pops.len();
+    let mut identities = vec![GeneIdentities::default(); n_pops * n_pops];
+    let mut n_loci = vec![0u64; n_pops * n_pops];
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        let counts: Vec<Vec<u64>> = pops
+            .iter()
+            .map(|pop| population_allele_counts(&var, pop))
+            .collect();
+        for pop_a in 0..n_pops {
+            for pop_b in pop_a + 1..n_pops {
+                if let Some(locus_identities) =
+                    identities_from_counts(&counts[pop_a], &counts[pop_b])
+                {
+                    identities[pop_a * n_pops + pop_b].add(&locus_identities);
+                    n_loci[pop_a * n_pops + pop_b] += 1;
+                }
+            }
+        }
+    }
+
+    let mut distances = Array2::zeros((n_pops, n_pops));
+    for pop_a in 0..n_pops {
+        for pop_b in pop_a + 1..n_pops {
+            let j = &mut identities[pop_a * n_pops + pop_b];
+            j.scale(1.0 / n_loci[pop_a * n_pops + pop_b] as f64);
+            let distance = metric.distance(j);
+            distances[[pop_a, pop_b]] = distance;
+            distances[[pop_b, pop_a]] = distance;
+        }
+    }
+    Ok(distances)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::parse_vcf_buffer_str;
+
+    // Pop A frequencies are (1, 0) in both loci, pop B ones (0.25, 0.75) and
+    // (1, 0). Pop A has no called allele in the third locus.
+    const VCF_NEI: &str = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2\tS3\tS4
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT\t0/0\t0/0\t0/1\t1/1
+1\t20\t.\tA\tG\t.\tPASS\t.\tGT\t0/0\t0/0\t0/0\t0/0
+1\t30\t.\tA\tG\t.\tPASS\t.\tGT\t./.\t./.\t0/0\t0/0";
+
+    const POP_A: [usize; 2] = [0, 1];
+    const POP_B: [usize; 2] = [2, 3];
+
+    #[test]
+    fn nei_distances() {
+        // Worked by hand from the allele frequencies, over the first two loci:
+        // Jx = (1 + 1) / 2 = 1, Jy = (0.625 + 1) / 2 = 0.8125 and
+        // Jxy = (0.25 + 1) / 2 = 0.625.
+
+        // Nei (1972), Am. Nat. 106: 283-292, D = -ln(0.625 / sqrt(0.8125)).
+        let mut vars = parse_vcf_buffer_str(VCF_NEI);
+        let distance = nei_standard_distance(&mut vars, &POP_A, &POP_B).unwrap();
+        assert!((distance - 0.36618394685661315).abs() < 1e-12);
+
+        // Nei (1973), PNAS 70: 3321-3323, Dm = (1 + 0.8125) / 2 - 0.625.
+        let mut vars = parse_vcf_buffer_str(VCF_NEI);
+        let distance = nei_minimum_distance(&mut vars, &POP_A, &POP_B).unwrap();
+        assert!((distance - 0.28125).abs() < 1e-12);
+
+        // Nei (1978), Genetics 89: 583-590. With 4 alleles per population,
+        // Jx' = (4 * 1 - 1) / 3 = 1 and Jy' = (4 * 0.625 - 1) / 3 = 0.5 in the
+        // first locus, so Jy' = (0.5 + 1) / 2 = 0.75 and
+        // D = -ln(0.625 / sqrt(0.75)).
+        let mut vars = parse_vcf_buffer_str(VCF_NEI);
+        let distance = nei_unbiased_distance(&mut vars, &POP_A, &POP_B).unwrap();
+        assert!((distance - 0.326162593019845).abs() < 1e-12);
+
+        let mut vars = parse_vcf_buffer_str(VCF_NEI);
+        let distance = nei_standard_distance(&mut vars, &POP_A, &POP_A).unwrap();
+        assert!(distance.abs() < 1e-12);
+    }
+
+    #[test]
+    fn chord() {
+        // sum(sqrt(x_i y_i)) is 0.5 in the first locus and 1 in the second.
+        let mut vars = parse_vcf_buffer_str(VCF_NEI);
+        let distance = chord_distance(&mut vars, &POP_A, &POP_B).unwrap();
+        assert!((distance - 1.0 / std::f64::consts::PI).abs() < 1e-12);
+
+        let mut vars = parse_vcf_buffer_str(VCF_NEI);
+        assert_eq!(chord_distance(&mut vars, &POP_B, &POP_B).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn distance_matrix() {
+        let pops = vec![POP_A.to_vec(), POP_B.to_vec(), vec![2]];
+        for metric in [
+            DistanceMetric::NeiStandard,
+            DistanceMetric::NeiMinimum,
+            DistanceMetric::Reynolds,
+            DistanceMetric::Dxy,
+        ] {
+            let mut vars = parse_vcf_buffer_str(VCF_NEI);
+            let distances = population_distance_matrix(&mut vars, &pops, metric).unwrap();
+            assert_eq!(distances.dim(), (3, 3));
+            assert_eq!(distances, distances.t());
+            assert!(distances.diag().iter().all(|distance| *distance == 0.0));
+        }
+
+        let mut vars = parse_vcf_buffer_str(VCF_NEI);
+        let distances =
+            population_distance_matrix(&mut vars, &pops, DistanceMetric::NeiMinimum).unwrap();
+        assert!((distances[[0, 1]] - 0.28125).abs() < 1e-12);
+
+        // Jxy = (0.25 + 1) / 2 for pops A and B.
+        let mut vars = parse_vcf_buffer_str(VCF_NEI);
+        let distances = population_distance_matrix(&mut vars, &pops, DistanceMetric::Dxy).unwrap();
+        assert!((distances[[0, 1]] - 0.375).abs() < 1e-12);
+
+        // theta = (1 + 0.8125 - 1.25) / (2 * 0.375) = 0.75
+        let mut vars = parse_vcf_buffer_str(VCF_NEI);
+        let distances =
+            population_distance_matrix(&mut vars, &pops, DistanceMetric::Reynolds).unwrap();
+        assert!((distances[[0, 1]] - -(0.25f64).ln()).abs() < 1e-12);
+    }
+}
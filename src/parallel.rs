@@ -0,0 +1,266 @@
+use crate::{read_vcf_file, VCFParseError, Variant, Variants};
+use rayon::prelude::*;
+use std::io::{self, BufRead, Read};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// Variants parsed ahead by a reader thread and waiting to be consumed.
+const CHANNEL_CAPACITY: usize = 1024;
+/// Chunks decompressed ahead by a decompression thread.
+const NUM_CHUNKS_AHEAD: usize = 4;
+/// Variants read before applying a function to them in parallel.
+const SITE_MAP_CHUNK_SIZE: usize = 4096;
+
+#[derive(thiserror::Error, Debug)]
+pub enum BatchReadError {
+    #[error("Failed to read {} files: {0:?}", .0.len())]
+    Files(Vec<(PathBuf, String)>),
+    #[error("The samples of `{0}` differ from those of the first file")]
+    DifferentSamples(PathBuf),
+}
+
+type HeaderResult = Result<(Vec<String>, u8, bool), String>;
+
+/// Parses the file in a new thread that sends the samples and ploidy, with its
+/// `ploidy_confident` flag, and then
+/// every variant, as long as the receivers are alive.
+fn spawn_reader(
+    fpath: PathBuf,
+) -> (
+    Receiver<HeaderResult>,
+    Receiver<Result<Variant, VCFParseError>>,
+) {
+    let (header_sender, header_receiver) = mpsc::sync_channel(1);
+    let (var_sender, var_receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+    thread::spawn(move || {
+        let vars = match read_vcf_file(&fpath) {
+            Ok(vars) => vars,
+            Err(e) => {
+                let _ = header_sender.send(Err(e.to_string()));
+                return;
+            }
+        };
+        if header_sender
+            .send(Ok((
+                vars.samples.clone(),
+                vars.ploidy,
+                vars.ploidy_confident,
+            )))
+            .is_err()
+        {
+            return;
+        }
+        for var in vars.vars_iter {
+            if var_sender.send(var).is_err() {
+                break;
+            }
+        }
+    });
+    (header_receiver, var_receiver)
+}
+
+/// Opens many VCF files, like the per-chromosome shards of a cohort, each one
+/// parsed ahead in its own thread.
+///
+/// Every file is opened before returning, so all the failures are reported
+/// together. All the files must have the same samples, in the same order.
+pub fn read_vcfs_parallel(
+    fpaths: &[PathBuf],
+) -> Result<Vec<(PathBuf, Variants<'static>)>, BatchReadError> {
+    let readers: Vec<_> = fpaths
+        .iter()
+        .map(|fpath| spawn_reader(fpath.clone()))
+        .collect();
+
+    let mut batch = Vec::new();
+    let mut errors = Vec::new();
+    for (fpath, (header_receiver, var_receiver)) in fpaths.iter().zip(readers) {
+        match header_receiver.recv() {
+            Ok(Ok((samples, ploidy, ploidy_confident))) => batch.push((
+                fpath.clone(),
+                Variants::new(samples, var_receiver.into_iter(), ploidy, ploidy_confident),
+            )),
+            Ok(Err(e)) => errors.push((fpath.clone(), e)),
+            Err(_) => errors.push((fpath.clone(), "The reader thread panicked".to_string())),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(BatchReadError::Files(errors));
+    }
+    if let Some((fpath, _)) = batch
+        .iter()
+        .find(|(_, vars)| vars.samples != batch[0].1.samples)
+    {
+        return Err(BatchReadError::DifferentSamples(fpath.clone()));
+    }
+    Ok(batch)
+}
+
+/// Applies `f` to every variant in the rayon thread pool, the parallel
+/// analogue of `reduce_variants` for per-site statistics.
+///
+/// The variants are read in chunks and the results are returned in the
+/// order of the variants, so all of them are kept in memory.
+pub fn parallel_site_map<T, F>(vars: Variants, f: F) -> Result<Vec<T>, VCFParseError>
+where
+    T: Send,
+    F: Fn(&Variant) -> T + Sync,
+{
+    let mut results = Vec::new();
+    let mut chunk = Vec::with_capacity(SITE_MAP_CHUNK_SIZE);
+    let mut vars_iter = vars.vars_iter;
+    while vars_iter.peek().is_some() {
+        chunk.clear();
+        for var in vars_iter.by_ref().take(SITE_MAP_CHUNK_SIZE) {
+            chunk.push(var?);
+        }
+        results.par_extend(chunk.par_iter().map(&f));
+    }
+    Ok(results)
+}
+
+/// Reads, in order, the chunks of data sent by a decompression thread.
+pub(crate) struct ChannelReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n_bytes = available.len().min(buf.len());
+        buf[..n_bytes].copy_from_slice(&available[..n_bytes]);
+        self.consume(n_bytes);
+        Ok(n_bytes)
+    }
+}
+
+impl BufRead for ChannelReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk?;
+                    self.pos = 0;
+                }
+                // The thread is done.
+                Err(_) => return Ok(&[]),
+            }
+        }
+        Ok(&self.chunk[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+/// Reads the decoder in a new thread, in chunks of up to `chunk_size` bytes.
+///
+/// A read error is sent as is and ends the thread, as does dropping the reader.
+pub(crate) fn decompress_in_thread<R: Read + Send + 'static>(
+    mut decoder: R,
+    chunk_size: usize,
+) -> ChannelReader {
+    let (sender, receiver) = mpsc::sync_channel(NUM_CHUNKS_AHEAD);
+    thread::spawn(move || loop {
+        let mut chunk = vec![0; chunk_size.max(1)];
+        let result = match decoder.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n_bytes) => {
+                chunk.truncate(n_bytes);
+                Ok(chunk)
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => Err(e),
+        };
+        let is_err = result.is_err();
+        if sender.send(result).is_err() || is_err {
+            break;
+        }
+    });
+    ChannelReader {
+        receiver,
+        chunk: Vec::new(),
+        pos: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{write_temp_vcf, VCF_MISSING};
+    use crate::VcfReaderBuilder;
+
+    #[test]
+    fn read_shards() {
+        let chrom20 = write_temp_vcf("parallel_20", VCF_MISSING);
+        let chrom21 = write_temp_vcf("parallel_21", &VCF_MISSING.replace("\n20\t", "\n21\t"));
+        let batch = read_vcfs_parallel(&[chrom20.clone(), chrom21.clone()]).unwrap();
+        assert_eq!(batch.len(), 2);
+        for ((fpath, vars), chrom) in batch.into_iter().zip(["20", "21"]) {
+            assert_eq!(vars.samples, vec!["S1", "S2", "S3"]);
+            let chroms: Vec<String> = vars.vars_iter.map(|var| var.unwrap().chrom).collect();
+            assert_eq!(chroms, vec![chrom, chrom], "{fpath:?}");
+        }
+
+        let missing = PathBuf::from("/nonexistent/nei_rs.vcf");
+        let empty = write_temp_vcf("parallel_empty", "");
+        match read_vcfs_parallel(&[chrom20.clone(), missing.clone(), empty.clone()]) {
+            Err(BatchReadError::Files(errors)) => {
+                let fpaths: Vec<PathBuf> = errors.into_iter().map(|(fpath, _)| fpath).collect();
+                assert_eq!(fpaths, vec![missing, empty]);
+            }
+            _ => panic!("Missing files not reported"),
+        }
+
+        let other_samples = write_temp_vcf("parallel_other", &VCF_MISSING.replace("\tS3", "\tS4"));
+        assert!(matches!(
+            read_vcfs_parallel(&[chrom20, other_samples]),
+            Err(BatchReadError::DifferentSamples(_))
+        ));
+    }
+
+    #[test]
+    fn site_map() {
+        let vars = crate::tests::parse_vcf_buffer_str(crate::tests::VCF_45);
+        let freqs = parallel_site_map(vars, |var| var.allele_frequencies()).unwrap();
+        let expected: Vec<Vec<f64>> = crate::tests::parse_vcf_str(crate::tests::VCF_45)
+            .iter()
+            .map(|var| var.allele_frequencies())
+            .collect();
+        assert_eq!(freqs, expected);
+        assert_eq!(freqs[0], vec![0.5, 0.5]);
+
+        let vars = crate::tests::parse_vcf_buffer_str(VCF_MISSING);
+        assert_eq!(
+            parallel_site_map(vars, |var| var.pos).unwrap(),
+            vec![100, 200]
+        );
+    }
+
+    #[test]
+    fn threaded_decompression() {
+        let fpath = PathBuf::from("tests/data/format_example_4_5.vcf.gz");
+        let positions =
+            |vars: Variants| -> Vec<u64> { vars.vars_iter.map(|var| var.unwrap().pos).collect() };
+        let threaded = VcfReaderBuilder::new()
+            .threaded_decompression(true)
+            .buffer_capacity(64)
+            .read_file(&fpath)
+            .unwrap();
+        let expected = positions(read_vcf_file(&fpath).unwrap());
+        assert_eq!(positions(threaded), expected);
+
+        let gzip = std::fs::read(&fpath).unwrap();
+        let truncated = std::env::temp_dir().join("nei_rs_truncated.vcf.gz");
+        std::fs::write(&truncated, &gzip[..gzip.len() - 20]).unwrap();
+        let vars = VcfReaderBuilder::new()
+            .threaded_decompression(true)
+            .read_file(&truncated)
+            .unwrap();
+        assert!(vars.vars_iter.last().unwrap().is_err());
+    }
+}
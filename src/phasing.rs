@@ -0,0 +1,288 @@
+use crate::stats::genotype_is_called;
+use crate::{AlleleInt, VCFParseError, Variant, Variants, VariantsIter};
+
+/// The phased heterozygous sites of a sample that belong to the same block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Haplotype {
+    pub chrom: String,
+    /// The PS of the block, None if it was delimited by the `|` separators.
+    pub phase_set: Option<u64>,
+    pub positions: Vec<u64>,
+    /// Per chromosome copy, the allele found at every position.
+    pub alleles: Vec<Vec<String>>,
+}
+
+impl Haplotype {
+    fn new(var: &Variant, phase_set: Option<u64>) -> Haplotype {
+        Haplotype {
+            chrom: var.chrom.clone(),
+            phase_set,
+            positions: Vec::new(),
+            alleles: vec![Vec::new(); var.ploidy as usize],
+        }
+    }
+
+    fn push(&mut self, var: &Variant, sample_idx: usize) {
+        self.positions.push(var.pos);
+        for (copy_alleles, allele) in self.alleles.iter_mut().zip(&var.gts[sample_idx]) {
+            copy_alleles.push(var.alleles[*allele as usize].clone());
+        }
+    }
+
+    /// The sequence of every chromosome copy, its alleles concatenated.
+    pub fn sequences(&self) -> Vec<String> {
+        self.alleles
+            .iter()
+            .map(|alleles| alleles.concat())
+            .collect()
+    }
+}
+
+/// Iterator over the haplotype blocks of a sample.
+pub struct PhaseBlocks<'a> {
+    vars_iter: VariantsIter<'a>,
+    sample_idx: usize,
+    block: Option<Haplotype>,
+}
+
+impl Iterator for PhaseBlocks<'_> {
+    type Item = Result<Haplotype, VCFParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let var = match self.vars_iter.next() {
+                Some(Ok(var)) => var,
+                Some(Err(e)) => return Some(Err(e)),
+                None => return self.block.take().map(Ok),
+            };
+            let gt = &var.gts[self.sample_idx];
+            let is_called = genotype_is_called(gt);
+            let is_het = is_called && gt.iter().any(|allele| *allele != gt[0]);
+            let is_phased_het = is_het && var.is_phased(self.sample_idx);
+            let phase_set = var.phase_set(self.sample_idx);
+
+            let is_block_end = match &self.block {
+                Some(block) if block.chrom != var.chrom => true,
+                Some(block) if is_phased_het => block.phase_set != phase_set,
+                // Without PS, any unphased heterozygous or missing genotype ends the block.
+                Some(block) => block.phase_set.is_none() && (is_het || !is_called),
+                None => false,
+            };
+            let finished_block = if is_block_end {
+                self.block.take()
+            } else {
+                None
+            };
+            if is_phased_het {
+                self.block
+                    .get_or_insert_with(|| Haplotype::new(&var, phase_set))
+                    .push(&var, self.sample_idx);
+            }
+            if let Some(block) = finished_block {
+                return Some(Ok(block));
+            }
+        }
+    }
+}
+
+/// Groups the phased heterozygous sites of a sample in haplotype blocks.
+///
+/// Sites with a PS FORMAT field are grouped by it, and those without one in
+/// runs of consecutive `|` genotypes, broken by any unphased heterozygous or
+/// missing genotype. Homozygous sites are not included, and blocks never span
+/// chromosomes. The input must be sorted.
+pub fn phase_blocks(vars: Variants, sample_idx: usize) -> PhaseBlocks {
+    PhaseBlocks {
+        vars_iter: vars.vars_iter,
+        sample_idx,
+        block: None,
+    }
+}
+
+/// Number of genotypes whose separators disagree, see
+/// `Variant::is_consistently_phased`.
+pub fn count_mixed_phasing(vars: &mut Variants) -> Result<u64, VCFParseError> {
+    let mut n_mixed = 0;
+    for var in vars.vars_iter.by_ref() {
+        n_mixed += var?.mixed_phasing.len() as u64;
+    }
+    Ok(n_mixed)
+}
+
+/// The (paternal, maternal) alleles of a child if only one assignment of its
+/// alleles is compatible with the genotypes of the parents.
+fn transmitted_alleles(
+    child: &[AlleleInt],
+    father: &[AlleleInt],
+    mother: &[AlleleInt],
+) -> Option<(AlleleInt, AlleleInt)> {
+    let mut assignments = vec![(child[0], child[1]), (child[1], child[0])];
+    assignments.dedup();
+    assignments
+        .retain(|(paternal, maternal)| father.contains(paternal) && mother.contains(maternal));
+    match assignments[..] {
+        [assignment] => Some(assignment),
+        _ => None,
+    }
+}
+
+/// Puts the transmitted allele first in the genotype of a parent.
+fn phase_parent(var: &mut Variant, parent_idx: usize, transmitted: AlleleInt) {
+    let gt = &mut var.gts[parent_idx];
+    if gt[0] != transmitted {
+        gt.swap(0, 1);
+    }
+    var.phased[parent_idx] = true;
+}
+
+/// Phases the trios, given as (child, father, mother) sample indexes, by
+/// Mendelian transmission, like GATK's PhaseByTransmission.
+///
+/// At biallelic sites with the three diploid genotypes called, the child is
+/// phased as paternal|maternal when only one assignment of its alleles is
+/// consistent with the parents, and the parents with the transmitted allele
+/// first. Sites where all three are heterozygous, and Mendelian errors, are
+/// left as they were. A parent in several trios is phased by the first one
+/// that resolves it. Phase sets are not changed.
+pub fn phase_by_transmission<'a>(
+    vars: Variants<'a>,
+    trios: &[(usize, usize, usize)],
+) -> Variants<'a> {
+    let trios = trios.to_vec();
+    let vars_iter = vars.vars_iter.map(move |var| {
+        let mut var = var?;
+        if var.alleles.len() != 2 {
+            return Ok(var);
+        }
+        let mut phased_parents = Vec::new();
+        for (child, father, mother) in trios.iter().copied() {
+            let gts = [&var.gts[child], &var.gts[father], &var.gts[mother]];
+            if gts
+                .iter()
+                .any(|gt| gt.len() != 2 || !genotype_is_called(gt))
+            {
+                continue;
+            }
+            let (paternal, maternal) = match transmitted_alleles(gts[0], gts[1], gts[2]) {
+                Some(alleles) => alleles,
+                None => continue,
+            };
+            var.gts[child] = vec![paternal, maternal];
+            var.phased[child] = true;
+            for (parent, transmitted) in [(father, paternal), (mother, maternal)] {
+                if !phased_parents.contains(&parent) {
+                    phase_parent(&mut var, parent, transmitted);
+                    phased_parents.push(parent);
+                }
+            }
+        }
+        Ok(var)
+    });
+    Variants::new(vars.samples, vars_iter, vars.ploidy, vars.ploidy_confident)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{parse_vcf_buffer_str, parse_vcf_str};
+
+    const VCF_PHASED: &str = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT:PS\t0|1:10\t0|1
+1\t20\t.\tC\tT\t.\tPASS\t.\tGT:PS\t1|0:10\t1|1
+1\t30\t.\tG\tGA\t.\tPASS\t.\tGT:PS\t0/1:.\t0/1
+1\t40\t.\tT\tC\t.\tPASS\t.\tGT:PS\t1|0:10\t1|0
+1\t50\t.\tA\tC\t.\tPASS\t.\tGT:PS\t0|1:50\t1|0
+2\t10\t.\tA\tG\t.\tPASS\t.\tGT\t0|1\t0|1";
+
+    fn blocks(sample_idx: usize) -> Vec<Haplotype> {
+        phase_blocks(parse_vcf_buffer_str(VCF_PHASED), sample_idx)
+            .map(|block| block.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn phase_set_blocks() {
+        let blocks = blocks(0);
+        let summary: Vec<(&str, Option<u64>, &Vec<u64>)> = blocks
+            .iter()
+            .map(|block| (block.chrom.as_str(), block.phase_set, &block.positions))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("1", Some(10), &vec![10, 20, 40]),
+                ("1", Some(50), &vec![50]),
+                ("2", None, &vec![10]),
+            ]
+        );
+        assert_eq!(blocks[0].sequences(), vec!["ATC", "GCT"]);
+    }
+
+    #[test]
+    fn contiguous_blocks() {
+        let blocks = blocks(1);
+        let positions: Vec<&Vec<u64>> = blocks.iter().map(|block| &block.positions).collect();
+        assert_eq!(positions, vec![&vec![10], &vec![40, 50], &vec![10]]);
+        assert!(blocks.iter().all(|block| block.phase_set.is_none()));
+        assert_eq!(
+            blocks[1].alleles,
+            vec![
+                vec!["C".to_string(), "C".to_string()],
+                vec!["T".to_string(), "A".to_string()]
+            ]
+        );
+    }
+
+    #[test]
+    fn trio_transmission() {
+        let vcf = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tCHILD\tFATHER\tMOTHER
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT\t1/0\t0/0\t1/1
+1\t20\t.\tA\tG\t.\tPASS\t.\tGT\t0/1\t1/1\t1/0
+1\t30\t.\tA\tG\t.\tPASS\t.\tGT\t0/1\t0/1\t0/1
+1\t40\t.\tA\tG\t.\tPASS\t.\tGT\t0/1\t0/0\t0/0
+1\t50\t.\tA\tG,T\t.\tPASS\t.\tGT\t1/2\t1/1\t2/2
+1\t60\t.\tA\tG\t.\tPASS\t.\tGT\t0/1\t./.\t1/1";
+        let vars: Vec<Variant> = phase_by_transmission(parse_vcf_buffer_str(vcf), &[(0, 1, 2)])
+            .vars_iter
+            .map(|var| var.unwrap())
+            .collect();
+        let is_phased = |var: &Variant| (0..3).map(|idx| var.is_phased(idx)).collect::<Vec<_>>();
+
+        assert_eq!(vars[0].gts, vec![vec![0, 1], vec![0, 0], vec![1, 1]]);
+        assert_eq!(is_phased(&vars[0]), vec![true; 3]);
+        assert_eq!(vars[1].gts, vec![vec![1, 0], vec![1, 1], vec![0, 1]]);
+        assert_eq!(is_phased(&vars[1]), vec![true; 3]);
+        // All heterozygous, a Mendelian error, multiallelic and missing.
+        for var in &vars[2..] {
+            assert_eq!(is_phased(var), vec![false; 3]);
+        }
+        assert_eq!(vars[4].gts[0], vec![1, 2]);
+    }
+
+    #[test]
+    fn mixed_phasing() {
+        let vcf = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2
+1\t10\t.\tA\tG,T\t.\tPASS\t.\tGT\t0|1/2|2\t0|1|1|0
+1\t20\t.\tC\tT\t.\tPASS\t.\tGT\t0/0/1/1\t0|1/1/0";
+        let vars = parse_vcf_str(vcf);
+        assert!(!vars[0].is_consistently_phased(0));
+        assert!(!vars[0].is_phased(0));
+        assert!(vars[0].is_consistently_phased(1));
+        assert!(vars[0].is_phased(1));
+        assert!(vars[1].is_consistently_phased(0));
+        assert!(!vars[1].is_consistently_phased(1));
+        assert_eq!(vars[0].gts[0], vec![0, 1, 2, 2]);
+
+        assert_eq!(
+            count_mixed_phasing(&mut parse_vcf_buffer_str(vcf)).unwrap(),
+            2
+        );
+        assert_eq!(
+            count_mixed_phasing(&mut parse_vcf_buffer_str(VCF_PHASED)).unwrap(),
+            0
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use crate::stats;
+use crate::{read_vcf_file, AlleleInt, FilterStatus, Variant, Variants};
+use pyo3::exceptions::{PyIOError, PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use std::path::PathBuf;
+
+fn open_vcf(fpath: PathBuf) -> PyResult<Variants<'static>> {
+    read_vcf_file(&fpath).map_err(|e| PyIOError::new_err(e.to_string()))
+}
+
+/// A parsed VCF record, its fields can be read as attributes or by key.
+#[pyclass(name = "Variant", frozen)]
+struct PyVariant {
+    var: Variant,
+}
+
+#[pymethods]
+impl PyVariant {
+    #[getter]
+    fn chrom(&self) -> &str {
+        &self.var.chrom
+    }
+
+    #[getter]
+    fn pos(&self) -> u64 {
+        self.var.pos
+    }
+
+    #[getter]
+    fn id(&self) -> &str {
+        &self.var.id
+    }
+
+    #[getter]
+    fn alleles(&self) -> Vec<String> {
+        self.var.alleles.clone()
+    }
+
+    #[getter]
+    fn qual(&self) -> f64 {
+        self.var.qual
+    }
+
+    #[getter]
+    fn filters(&self) -> Vec<String> {
+        match self.var.filter_status() {
+            FilterStatus::Failed(names) => names.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    #[getter]
+    fn genotypes(&self) -> Vec<Vec<AlleleInt>> {
+        self.var.gts.clone()
+    }
+
+    #[getter]
+    fn ploidy(&self) -> u8 {
+        self.var.ploidy
+    }
+
+    fn keys(&self) -> Vec<&'static str> {
+        vec![
+            "chrom",
+            "pos",
+            "id",
+            "alleles",
+            "qual",
+            "filters",
+            "genotypes",
+            "ploidy",
+        ]
+    }
+
+    fn __getitem__<'py>(slf: &Bound<'py, Self>, key: &str) -> PyResult<Bound<'py, PyAny>> {
+        if !slf.get().keys().contains(&key) {
+            return Err(PyKeyError::new_err(key.to_string()));
+        }
+        slf.getattr(key)
+    }
+
+    fn allele_frequencies(&self, samples: Vec<usize>) -> PyResult<Vec<f64>> {
+        check_samples(&self.var, &samples)?;
+        Ok(stats::population_allele_freqs(&self.var, &samples))
+    }
+
+    fn nei_diversity(&self, samples: Vec<usize>) -> PyResult<f64> {
+        check_samples(&self.var, &samples)?;
+        Ok(stats::nei_diversity(&self.var, &samples))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Variant(chrom={:?}, pos={}, alleles={:?})",
+            self.var.chrom, self.var.pos, self.var.alleles
+        )
+    }
+}
+
+fn check_samples(var: &Variant, samples: &[usize]) -> PyResult<()> {
+    match samples
+        .iter()
+        .find(|sample_idx| **sample_idx >= var.gts.len())
+    {
+        Some(sample_idx) => Err(PyValueError::new_err(format!(
+            "Sample index {sample_idx} out of range"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Iterator over the variants of a VCF file.
+#[pyclass(name = "Variants", unsendable)]
+struct PyVariants {
+    vars: Variants<'static>,
+}
+
+#[pymethods]
+impl PyVariants {
+    #[getter]
+    fn samples(&self) -> Vec<String> {
+        self.vars.samples.clone()
+    }
+
+    #[getter]
+    fn ploidy(&self) -> u8 {
+        self.vars.ploidy
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<PyVariant>> {
+        match self.vars.vars_iter.next() {
+            Some(Ok(var)) => Ok(Some(PyVariant { var })),
+            Some(Err(e)) => Err(PyValueError::new_err(e.to_string())),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Opens a plain or gzipped VCF file and returns an iterator over its variants.
+#[pyfunction(name = "read_vcf_file")]
+fn py_read_vcf_file(fpath: PathBuf) -> PyResult<PyVariants> {
+    Ok(PyVariants {
+        vars: open_vcf(fpath)?,
+    })
+}
+
+/// Nucleotide diversity of the samples summed over all the variants of a file.
+#[pyfunction(name = "pi")]
+fn py_pi(fpath: PathBuf, samples: Vec<usize>) -> PyResult<f64> {
+    let mut vars = open_vcf(fpath)?;
+    if let Some(sample_idx) = samples
+        .iter()
+        .find(|sample_idx| **sample_idx >= vars.samples.len())
+    {
+        return Err(PyValueError::new_err(format!(
+            "Sample index {sample_idx} out of range"
+        )));
+    }
+    stats::pi(&mut vars, &samples).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+#[pymodule]
+fn nei_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyVariant>()?;
+    m.add_class::<PyVariants>()?;
+    m.add_function(wrap_pyfunction!(py_read_vcf_file, m)?)?;
+    m.add_function(wrap_pyfunction!(py_pi, m)?)?;
+    Ok(())
+}
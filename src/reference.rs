@@ -0,0 +1,227 @@
+use crate::{VCFParseError, Variants};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+/// A source of reference bases.
+pub trait ReferenceSequence {
+    /// The sequence of `len` bases that starts at the 1-based `pos`.
+    fn sequence(&self, chrom: &str, pos: u64, len: u64) -> io::Result<String>;
+}
+
+/// Whole chromosome sequences kept in memory, by name.
+impl ReferenceSequence for HashMap<String, String> {
+    fn sequence(&self, chrom: &str, pos: u64, len: u64) -> io::Result<String> {
+        let start = pos.saturating_sub(1) as usize;
+        self.get(chrom)
+            .ok_or_else(|| chrom_not_found(chrom))?
+            .get(start..start + len as usize)
+            .map(str::to_string)
+            .ok_or_else(|| beyond_end(chrom, pos))
+    }
+}
+
+fn chrom_not_found(chrom: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("Chromosome `{chrom}` not found in the reference"),
+    )
+}
+
+fn beyond_end(chrom: &str, pos: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        format!("{chrom}:{pos} is beyond the end of the reference sequence"),
+    )
+}
+
+/// A line of a `.fai` index.
+struct FaiRecord {
+    length: u64,
+    offset: u64,
+    line_bases: u64,
+    line_width: u64,
+}
+
+/// A plain text FASTA file with its samtools `.fai` index, next to it.
+pub struct IndexedFasta {
+    file: RefCell<BufReader<File>>,
+    index: HashMap<String, FaiRecord>,
+}
+
+impl IndexedFasta {
+    pub fn open(fasta: &PathBuf) -> Result<IndexedFasta, Box<dyn Error>> {
+        let mut fai = fasta.clone().into_os_string();
+        fai.push(".fai");
+        let mut index = HashMap::new();
+        for (line_idx, line) in BufReader::new(File::open(&fai)?).lines().enumerate() {
+            let line = line?;
+            let fields: Vec<&str> = line.split('\t').collect();
+            let numbers: Vec<u64> = fields
+                .get(1..5)
+                .unwrap_or(&[])
+                .iter()
+                .filter_map(|field| field.parse().ok())
+                .collect();
+            match numbers[..] {
+                [length, offset, line_bases, line_width]
+                    if line_bases > 0 && line_width >= line_bases =>
+                {
+                    let record = FaiRecord {
+                        length,
+                        offset,
+                        line_bases,
+                        line_width,
+                    };
+                    index.insert(fields[0].to_string(), record);
+                }
+                _ => return Err(format!("Invalid fai line {}", line_idx + 1).into()),
+            }
+        }
+        Ok(IndexedFasta {
+            file: RefCell::new(BufReader::new(File::open(fasta)?)),
+            index,
+        })
+    }
+
+    /// The sequence of `len` bases that starts at the 1-based `pos`.
+    pub fn fetch(&self, chrom: &str, pos: u64, len: u64) -> io::Result<String> {
+        let record = self
+            .index
+            .get(chrom)
+            .ok_or_else(|| chrom_not_found(chrom))?;
+        let start = pos.saturating_sub(1);
+        if start + len > record.length {
+            return Err(beyond_end(chrom, pos));
+        }
+        let line_ends = record.line_width - record.line_bases;
+        let byte_offset = |base: u64| {
+            record.offset + base / record.line_bases * record.line_width + base % record.line_bases
+        };
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(byte_offset(start)))?;
+        let n_bytes = byte_offset(start + len) - byte_offset(start);
+        let mut bytes = Vec::with_capacity(n_bytes as usize);
+        (&mut *file).take(n_bytes).read_to_end(&mut bytes)?;
+        if line_ends > 0 {
+            bytes.retain(|byte| *byte != b'\n' && *byte != b'\r');
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+impl ReferenceSequence for IndexedFasta {
+    fn sequence(&self, chrom: &str, pos: u64, len: u64) -> io::Result<String> {
+        self.fetch(chrom, pos, len)
+    }
+}
+
+/// Checks REF against the reference sequence, ignoring case, yielding a
+/// `ReferenceMismatch` error for every variant in which they differ.
+///
+/// The FASTA must be uncompressed and indexed with `samtools faidx`.
+pub fn validate_against_reference<'a>(
+    vars: Variants<'a>,
+    fasta: &PathBuf,
+) -> Result<Variants<'a>, Box<dyn Error>> {
+    let reference = IndexedFasta::open(fasta)?;
+    let vars_iter = vars.vars_iter.map(move |var| {
+        let var = var?;
+        let found = var.ref_allele();
+        let expected = reference
+            .fetch(&var.chrom, var.pos, found.len() as u64)
+            .map_err(|e| VCFParseError::ReferenceReadError(e.to_string()))?;
+        if !expected.eq_ignore_ascii_case(found) {
+            return Err(VCFParseError::ReferenceMismatch {
+                chrom: var.chrom.clone(),
+                pos: var.pos,
+                expected,
+                found: found.to_string(),
+            });
+        }
+        Ok(var)
+    });
+    Ok(Variants::new(
+        vars.samples,
+        vars_iter,
+        vars.ploidy,
+        vars.ploidy_confident,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{parse_vcf_buffer_str, VCF_MISSING};
+
+    /// Chromosome 20, 250 bp in lines of 60, with A at 100 and C at 200.
+    fn write_fasta() -> PathBuf {
+        let mut seq = vec![b't'; 250];
+        seq[99] = b'A';
+        seq[199] = b'C';
+        let mut fasta = b">20\n".to_vec();
+        for line in seq.chunks(60) {
+            fasta.extend(line);
+            fasta.push(b'\n');
+        }
+        let fpath = std::env::temp_dir().join("nei_rs_reference.fa");
+        std::fs::write(&fpath, fasta).unwrap();
+        std::fs::write(fpath.with_extension("fa.fai"), "20\t250\t4\t60\t61\n").unwrap();
+        fpath
+    }
+
+    fn validate(vcf: &str, fasta: &PathBuf) -> Vec<Result<u64, VCFParseError>> {
+        validate_against_reference(parse_vcf_buffer_str(vcf), fasta)
+            .unwrap()
+            .vars_iter
+            .map(|var| var.map(|var| var.pos))
+            .collect()
+    }
+
+    #[test]
+    fn reference_mismatches() {
+        let fasta = write_fasta();
+        let reference = IndexedFasta::open(&fasta).unwrap();
+        assert_eq!(reference.fetch("20", 59, 4).unwrap(), "tttt");
+        assert_eq!(reference.fetch("20", 99, 3).unwrap(), "tAt");
+
+        let results = validate(VCF_MISSING, &fasta);
+        assert!(results.iter().all(|result| result.is_ok()));
+
+        // The reference is soft-masked.
+        let results = validate(&VCF_MISSING.replace("\tC\tT\t", "\tCTT\tT\t"), &fasta);
+        assert!(results.iter().all(|result| result.is_ok()));
+
+        let results = validate(&VCF_MISSING.replace("\tC\tT\t", "\tGTT\tT\t"), &fasta);
+        match &results[1] {
+            Err(VCFParseError::ReferenceMismatch {
+                pos,
+                expected,
+                found,
+                ..
+            }) => assert_eq!(
+                (*pos, expected.as_str(), found.as_str()),
+                (200, "Ctt", "GTT")
+            ),
+            result => panic!("Unexpected result: {result:?}"),
+        }
+
+        let results = validate(&VCF_MISSING.replace("\n20\t", "\n21\t"), &fasta);
+        assert!(matches!(
+            results[0],
+            Err(VCFParseError::ReferenceReadError(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_fai() {
+        let fpath = std::env::temp_dir().join("nei_rs_bad_index.fa");
+        std::fs::write(&fpath, ">20\nACGT\n").unwrap();
+        std::fs::write(fpath.with_extension("fa.fai"), "20\t4\t4\t61\t60\n").unwrap();
+        let error = IndexedFasta::open(&fpath).err().unwrap();
+        assert_eq!(error.to_string(), "Invalid fai line 1");
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{sniff_stream, Variants, VcfFileKind, VcfReaderBuilder, DEFAULT_BUFFER_CAPACITY};
+use flate2::bufread::MultiGzDecoder;
+use std::error::Error;
+use std::io::BufReader;
+
+#[derive(thiserror::Error, Debug)]
+pub enum RemoteReadError {
+    #[error("Request to `{url}` failed with HTTP status {status}")]
+    HttpStatus { url: String, status: u16 },
+}
+
+/// Streams a plain or gzipped VCF from an HTTP(S) URL, following redirects.
+///
+/// The file is parsed as it is downloaded, and gzip, or zstd with the `zstd`
+/// feature, is detected by its magic bytes, see `sniff_stream`, not by the URL. Any final status other than 200 is an error.
+pub fn read_vcf_url(url: &str) -> Result<Variants<'static>, Box<dyn Error>> {
+    let response = match ureq::get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::StatusCode(status)) => {
+            return Err(Box::new(RemoteReadError::HttpStatus {
+                url: url.to_string(),
+                status,
+            }))
+        }
+        Err(e) => return Err(Box::new(e)),
+    };
+    if response.status() != 200 {
+        return Err(Box::new(RemoteReadError::HttpStatus {
+            url: url.to_string(),
+            status: response.status().as_u16(),
+        }));
+    }
+
+    let (kind, body) = sniff_stream(response.into_body().into_reader(), url)?;
+    let body = BufReader::with_capacity(DEFAULT_BUFFER_CAPACITY, body);
+    let options = VcfReaderBuilder::default();
+    match kind {
+        VcfFileKind::PlainTextVcf => Ok(options.read_buffer(body)?),
+        VcfFileKind::GzippedVcf => {
+            let decoder = MultiGzDecoder::new(body);
+            Ok(options.read_buffer(BufReader::with_capacity(DEFAULT_BUFFER_CAPACITY, decoder))?)
+        }
+        #[cfg(feature = "zstd")]
+        VcfFileKind::ZstdVcf => {
+            let decoder = zstd::Decoder::with_buffer(body)?;
+            Ok(options.read_buffer(BufReader::with_capacity(DEFAULT_BUFFER_CAPACITY, decoder))?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::VCF_MISSING;
+    use crate::VCFParseError;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serves a few fixed paths over HTTP, one connection at a time.
+    fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let gzipped = std::fs::read("tests/data/format_example_4_5.vcf.gz").unwrap();
+        let zstd = std::fs::read("tests/data/format_example_4_5.vcf.zst").unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 1024];
+                let n_bytes = stream.read(&mut request).unwrap();
+                let request = String::from_utf8_lossy(&request[..n_bytes]);
+                let path = request.split(' ').nth(1).unwrap_or("");
+                let (status, headers, body) = match path {
+                    "/missing.vcf" => ("200 OK", "", VCF_MISSING.as_bytes().to_vec()),
+                    "/example" => ("200 OK", "", gzipped.clone()),
+                    "/example.zst" => ("200 OK", "", zstd.clone()),
+                    "/moved.vcf" => ("302 Found", "Location: /missing.vcf\r\n", Vec::new()),
+                    "/not_a.vcf" => ("200 OK", "", b"<html></html>".to_vec()),
+                    _ => ("404 Not Found", "", Vec::new()),
+                };
+                let head = format!(
+                    "HTTP/1.1 {status}\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(head.as_bytes()).unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+        address
+    }
+
+    fn positions(vars: Variants) -> Vec<u64> {
+        vars.vars_iter.map(|var| var.unwrap().pos).collect()
+    }
+
+    #[test]
+    fn read_urls() {
+        let address = serve();
+        let vars = read_vcf_url(&format!("{address}/missing.vcf")).unwrap();
+        assert_eq!(vars.samples, vec!["S1", "S2", "S3"]);
+        assert_eq!(positions(vars), vec![100, 200]);
+
+        let vars = read_vcf_url(&format!("{address}/example")).unwrap();
+        assert_eq!(positions(vars).len(), 6);
+
+        #[cfg(feature = "zstd")]
+        {
+            let vars = read_vcf_url(&format!("{address}/example.zst")).unwrap();
+            assert_eq!(positions(vars).len(), 6);
+        }
+
+        let vars = read_vcf_url(&format!("{address}/moved.vcf")).unwrap();
+        assert_eq!(positions(vars), vec![100, 200]);
+
+        let error = read_vcf_url(&format!("{address}/nonexistent.vcf"))
+            .err()
+            .unwrap();
+        match error.downcast_ref::<RemoteReadError>() {
+            Some(RemoteReadError::HttpStatus { status, .. }) => assert_eq!(*status, 404),
+            None => panic!("Unexpected error: {error}"),
+        }
+
+        let error = read_vcf_url(&format!("{address}/not_a.vcf")).err().unwrap();
+        assert!(error.downcast_ref::<VCFParseError>().is_some());
+    }
+}
@@ -0,0 +1,1286 @@
+use crate::{AlleleInt, VCFParseError, Variant, Variants, MISSING_ALLELE};
+use ndarray::Array2;
+use std::collections::HashMap;
+
+pub(crate) fn genotype_is_called(gt: &[AlleleInt]) -> bool {
+    gt.iter().all(|allele| *allele != MISSING_ALLELE)
+}
+
+fn genotype_is_het(gt: &[AlleleInt]) -> bool {
+    genotype_is_called(gt) && gt.iter().any(|allele| *allele != gt[0])
+}
+
+/// Counts of each allele among the non-missing alleles of the given samples.
+pub fn population_allele_counts(var: &Variant, samples: &[usize]) -> Vec<u64> {
+    let mut counts = vec![0; var.alleles.len()];
+    for sample_idx in samples {
+        for allele in &var.gts[*sample_idx] {
+            if *allele == MISSING_ALLELE {
+                continue;
+            }
+            let allele = *allele as usize;
+            if allele >= counts.len() {
+                counts.resize(allele + 1, 0);
+            }
+            counts[allele] += 1;
+        }
+    }
+    counts
+}
+
+/// Allele frequencies among the given samples, empty if every allele is missing.
+pub fn population_allele_freqs(var: &Variant, samples: &[usize]) -> Vec<f64> {
+    let counts = population_allele_counts(var, samples);
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return Vec::new();
+    }
+    counts
+        .iter()
+        .map(|count| *count as f64 / total as f64)
+        .collect()
+}
+
+/// Number of genotypes, over all the variants and samples, of every ploidy.
+///
+/// Only files read with `VcfReaderBuilder::mixed_ploidy` can have more than one.
+pub fn ploidy_histogram(vars: &mut Variants) -> Result<HashMap<u8, u64>, VCFParseError> {
+    let mut histogram = HashMap::new();
+    for var in vars.vars_iter.by_ref() {
+        for gt in &var?.gts {
+            *histogram.entry(gt.len() as u8).or_insert(0) += 1;
+        }
+    }
+    Ok(histogram)
+}
+
+/// Genotype counts of a sample, for quality control.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleSummary {
+    pub name: String,
+    pub n_called: u64,
+    /// Genotypes with any missing allele.
+    pub n_missing: u64,
+    pub n_het: u64,
+    pub n_hom_alt: u64,
+    /// Heterozygous fraction of the called genotypes, NaN if none is called.
+    pub het_rate: f64,
+    /// Missing fraction of the genotypes, NaN if there are no variants.
+    pub missing_rate: f64,
+}
+
+/// The genotype counts of every sample, in a single pass over the variants.
+pub fn sample_report(vars: &mut Variants) -> Result<Vec<SampleSummary>, VCFParseError> {
+    let mut summaries: Vec<SampleSummary> = vars
+        .samples
+        .iter()
+        .map(|name| SampleSummary {
+            name: name.clone(),
+            n_called: 0,
+            n_missing: 0,
+            n_het: 0,
+            n_hom_alt: 0,
+            het_rate: f64::NAN,
+            missing_rate: f64::NAN,
+        })
+        .collect();
+    for var in vars.vars_iter.by_ref() {
+        for (summary, gt) in summaries.iter_mut().zip(&var?.gts) {
+            if !genotype_is_called(gt) {
+                summary.n_missing += 1;
+                continue;
+            }
+            summary.n_called += 1;
+            if genotype_is_het(gt) {
+                summary.n_het += 1;
+            } else if gt[0] != 0 {
+                summary.n_hom_alt += 1;
+            }
+        }
+    }
+    for summary in summaries.iter_mut() {
+        summary.het_rate = summary.n_het as f64 / summary.n_called as f64;
+        summary.missing_rate =
+            summary.n_missing as f64 / (summary.n_called + summary.n_missing) as f64;
+    }
+    Ok(summaries)
+}
+
+/// Whether a REF/ALT pair of single bases is a transition, None if it is not a SNP.
+fn is_transition(ref_allele: &str, alt_allele: &str) -> Option<bool> {
+    let (ref_base, alt_base) = match (ref_allele.as_bytes(), alt_allele.as_bytes()) {
+        ([ref_base], [alt_base]) => (ref_base.to_ascii_uppercase(), alt_base.to_ascii_uppercase()),
+        _ => return None,
+    };
+    if ref_base == alt_base || !b"ACGT".contains(&ref_base) || !b"ACGT".contains(&alt_base) {
+        return None;
+    }
+    Some(matches!(
+        (ref_base, alt_base),
+        (b'A', b'G') | (b'G', b'A') | (b'C', b'T') | (b'T', b'C')
+    ))
+}
+
+/// Summary of the variants of a chromosome.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChromStats {
+    pub chrom: String,
+    pub n_variants: u64,
+    /// Mean of the QUAL values, missing ones (0) excluded. NaN if all are missing.
+    pub mean_qual: f64,
+    /// Transitions over transversions among the SNP ALTs.
+    pub ts_tv: f64,
+    /// Mean over the variants of the fraction of samples with a called genotype.
+    pub mean_call_rate: f64,
+}
+
+/// Statistics of every chromosome, in order of appearance, in a single pass.
+///
+/// The input does not need to be sorted.
+pub fn per_chrom_stats(vars: &mut Variants) -> Result<Vec<ChromStats>, VCFParseError> {
+    struct Accumulator {
+        chrom: String,
+        n_variants: u64,
+        qual_sum: f64,
+        n_quals: u64,
+        n_transitions: u64,
+        n_transversions: u64,
+        call_rate_sum: f64,
+    }
+    let n_samples = vars.samples.len() as f64;
+    let mut chrom_idxs: HashMap<String, usize> = HashMap::new();
+    let mut accumulators: Vec<Accumulator> = Vec::new();
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        let idx = match chrom_idxs.get(&var.chrom) {
+            Some(idx) => *idx,
+            None => {
+                chrom_idxs.insert(var.chrom.clone(), accumulators.len());
+                accumulators.push(Accumulator {
+                    chrom: var.chrom.clone(),
+                    n_variants: 0,
+                    qual_sum: 0.0,
+                    n_quals: 0,
+                    n_transitions: 0,
+                    n_transversions: 0,
+                    call_rate_sum: 0.0,
+                });
+                accumulators.len() - 1
+            }
+        };
+        let accumulator = &mut accumulators[idx];
+        accumulator.n_variants += 1;
+        if var.qual != 0.0 {
+            accumulator.qual_sum += var.qual;
+            accumulator.n_quals += 1;
+        }
+        for alt_allele in var.alt_alleles() {
+            match is_transition(var.ref_allele(), alt_allele) {
+                Some(true) => accumulator.n_transitions += 1,
+                Some(false) => accumulator.n_transversions += 1,
+                None => {}
+            }
+        }
+        accumulator.call_rate_sum += var.called_samples() as f64 / n_samples;
+    }
+    Ok(accumulators
+        .into_iter()
+        .map(|accumulator| ChromStats {
+            chrom: accumulator.chrom,
+            n_variants: accumulator.n_variants,
+            mean_qual: accumulator.qual_sum / accumulator.n_quals as f64,
+            ts_tv: accumulator.n_transitions as f64 / accumulator.n_transversions as f64,
+            mean_call_rate: accumulator.call_rate_sum / accumulator.n_variants as f64,
+        })
+        .collect())
+}
+
+/// Summary of the values of a numeric INFO field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldStats {
+    pub count: u64,
+    /// NaN if there are no values.
+    pub mean: f64,
+    /// Sample variance, with `count - 1` degrees of freedom. NaN if there are
+    /// less than two values.
+    pub variance: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Statistics of the values of an INFO key in a single pass, using Welford's
+/// algorithm for the variance.
+///
+/// Missing and non-numeric values, including lists, are skipped.
+pub fn info_field_stats(vars: &mut Variants, key: &str) -> Result<FieldStats, VCFParseError> {
+    let mut stats = FieldStats {
+        count: 0,
+        mean: f64::NAN,
+        variance: f64::NAN,
+        min: f64::NAN,
+        max: f64::NAN,
+    };
+    let mut sum_sq_deviations = 0.0;
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        let value: f64 = match var.info(key).and_then(|value| value.parse().ok()) {
+            Some(value) => value,
+            None => continue,
+        };
+        stats.count += 1;
+        if stats.count == 1 {
+            (stats.mean, stats.min, stats.max) = (value, value, value);
+            continue;
+        }
+        let delta = value - stats.mean;
+        stats.mean += delta / stats.count as f64;
+        sum_sq_deviations += delta * (value - stats.mean);
+        stats.min = stats.min.min(value);
+        stats.max = stats.max.max(value);
+    }
+    if stats.count > 1 {
+        stats.variance = sum_sq_deviations / (stats.count - 1) as f64;
+    }
+    Ok(stats)
+}
+
+/// Classification of the sites by their polymorphism in two populations, as
+/// fractions of the sites with called alleles in both.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharingStats {
+    pub n_sites: u64,
+    /// Polymorphic in both populations.
+    pub shared: f64,
+    /// Monomorphic in both populations, for different alleles.
+    pub fixed_differences: f64,
+    /// Polymorphic only in population A.
+    pub exclusive_a: f64,
+    /// Polymorphic only in population B.
+    pub exclusive_b: f64,
+}
+
+/// Shared and exclusive polymorphisms, and fixed differences, between two
+/// populations. The fractions are NaN if no site has called alleles in both.
+pub fn cross_population_sharing(
+    vars: &mut Variants,
+    pop_a: &[usize],
+    pop_b: &[usize],
+) -> Result<SharingStats, VCFParseError> {
+    let mut n_sites = 0;
+    let (mut n_shared, mut n_fixed, mut n_exclusive_a, mut n_exclusive_b) = (0, 0, 0, 0);
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        let freqs_a = population_allele_freqs(&var, pop_a);
+        let freqs_b = population_allele_freqs(&var, pop_b);
+        if freqs_a.is_empty() || freqs_b.is_empty() {
+            continue;
+        }
+        n_sites += 1;
+        let fixed_allele = |freqs: &[f64]| freqs.iter().position(|freq| *freq == 1.0);
+        match (fixed_allele(&freqs_a), fixed_allele(&freqs_b)) {
+            (None, None) => n_shared += 1,
+            (Some(allele_a), Some(allele_b)) if allele_a != allele_b => n_fixed += 1,
+            (None, Some(_)) => n_exclusive_a += 1,
+            (Some(_), None) => n_exclusive_b += 1,
+            _ => {}
+        }
+    }
+    let fraction = |count: u64| count as f64 / n_sites as f64;
+    Ok(SharingStats {
+        n_sites,
+        shared: fraction(n_shared),
+        fixed_differences: fraction(n_fixed),
+        exclusive_a: fraction(n_exclusive_a),
+        exclusive_b: fraction(n_exclusive_b),
+    })
+}
+
+/// Nei's unbiased gene diversity of a site, `n/(n-1) * (1 - sum(p_i^2))`.
+///
+/// `n` is the number of called alleles in the samples; NaN if `n < 2`.
+pub fn nei_diversity(var: &Variant, samples: &[usize]) -> f64 {
+    let counts = population_allele_counts(var, samples);
+    let n_alleles: u64 = counts.iter().sum();
+    if n_alleles < 2 {
+        return f64::NAN;
+    }
+    let n_alleles = n_alleles as f64;
+    let sum_sq_freqs: f64 = counts
+        .iter()
+        .map(|count| (*count as f64 / n_alleles).powi(2))
+        .sum();
+    n_alleles / (n_alleles - 1.0) * (1.0 - sum_sq_freqs)
+}
+
+/// Nucleotide diversity: the average number of pairwise differences between
+/// the chromosomes of the samples, summed over all the variants.
+///
+/// Divide by the length of the sequenced region to get per-bp pi. Sites with
+/// less than two called alleles add nothing.
+pub fn pi(vars: &mut Variants, samples: &[usize]) -> Result<f64, VCFParseError> {
+    let mut pi = 0.0;
+    for var in vars.vars_iter.by_ref() {
+        let diversity = nei_diversity(&var?, samples);
+        if !diversity.is_nan() {
+            pi += diversity;
+        }
+    }
+    Ok(pi)
+}
+
+/// Nucleotide diversity of every chromosome, in order of appearance, see `pi`.
+///
+/// The sum is divided by the length of the chromosome in `contig_lengths`, as
+/// parsed from the `##contig` header lines, to get per-bp pi. Chromosomes
+/// without a length report the sum. The input does not need to be sorted.
+pub fn pi_per_chromosome(
+    vars: &mut Variants,
+    samples: &[usize],
+    contig_lengths: &HashMap<String, u64>,
+) -> Result<Vec<(String, f64)>, VCFParseError> {
+    let mut chrom_idxs: HashMap<String, usize> = HashMap::new();
+    let mut pis: Vec<(String, f64)> = Vec::new();
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        let idx = match chrom_idxs.get(&var.chrom) {
+            Some(idx) => *idx,
+            None => {
+                chrom_idxs.insert(var.chrom.clone(), pis.len());
+                pis.push((var.chrom.clone(), 0.0));
+                pis.len() - 1
+            }
+        };
+        let diversity = nei_diversity(&var, samples);
+        if !diversity.is_nan() {
+            pis[idx].1 += diversity;
+        }
+    }
+    for (chrom, pi) in pis.iter_mut() {
+        if let Some(length) = contig_lengths.get(chrom) {
+            *pi /= *length as f64;
+        }
+    }
+    Ok(pis)
+}
+
+/// Number of sites of each kind in a file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SiteCounts {
+    /// Sites with two observed alleles.
+    pub biallelic: u64,
+    /// Sites with more than two observed alleles.
+    pub multiallelic: u64,
+    /// Sites with less than two observed alleles, whatever their ALTs.
+    pub monomorphic: u64,
+    /// Sites with an ALT of a length different from REF, symbolic ALTs aside.
+    pub indels: u64,
+    /// Sites with ALTs and only one-base alleles.
+    pub snps: u64,
+}
+
+/// Counts the sites by number of alleles observed among the called genotypes,
+/// and by the kind of their REF and ALTs.
+pub fn site_type_counts(vars: &mut Variants) -> Result<SiteCounts, VCFParseError> {
+    let mut site_counts = SiteCounts::default();
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        let n_observed = var
+            .allele_counts()
+            .iter()
+            .filter(|count| **count > 0)
+            .count();
+        match n_observed {
+            0 | 1 => site_counts.monomorphic += 1,
+            2 => site_counts.biallelic += 1,
+            _ => site_counts.multiallelic += 1,
+        }
+
+        let ref_len = var.ref_allele().len();
+        let alts = var.alt_alleles();
+        if alts.is_empty() {
+            continue;
+        }
+        if ref_len == 1
+            && alts
+                .iter()
+                .all(|alt| alt.len() == 1 && alt != SPANNING_DELETION_ALLELE)
+        {
+            site_counts.snps += 1;
+        } else if alts.iter().any(|alt| {
+            !alt.starts_with('<') && alt != SPANNING_DELETION_ALLELE && alt.len() != ref_len
+        }) {
+            site_counts.indels += 1;
+        }
+    }
+    Ok(site_counts)
+}
+
+/// Allele of the records of a site spanned by an upstream deletion.
+const SPANNING_DELETION_ALLELE: &str = "*";
+
+/// Allele frequencies of every variant over the samples that do not carry a
+/// `*` allele.
+///
+/// A `*` stands for a deletion found in an upstream record, and that deletion
+/// is already counted in its own record, so, at the spanned site, the samples
+/// with a `*` copy are excluded from the denominator and `*` gets a frequency
+/// of 0. Each record is corrected on its own, the `*` genotypes are not matched
+/// to the deletion that originates them. Sites without other called samples
+/// get an empty vector.
+pub fn spanning_aware_af(vars: &[Variant]) -> Vec<Vec<f64>> {
+    vars.iter()
+        .map(|var| {
+            let spanning_idx = var
+                .alleles
+                .iter()
+                .position(|allele| allele == SPANNING_DELETION_ALLELE);
+            let samples: Vec<usize> = (0..var.gts.len())
+                .filter(|sample_idx| {
+                    spanning_idx.is_none_or(|spanning_idx| {
+                        !var.gts[*sample_idx]
+                            .iter()
+                            .any(|allele| *allele as usize == spanning_idx)
+                    })
+                })
+                .collect();
+            population_allele_freqs(var, &samples)
+        })
+        .collect()
+}
+
+/// Mean effective number of alleles over the polymorphic sites, NaN if there are none.
+pub fn mean_effective_num_alleles(vars: &mut Variants) -> Result<f64, VCFParseError> {
+    let mut sum = 0.0;
+    let mut n_sites = 0;
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        let n_observed = var
+            .allele_frequencies()
+            .iter()
+            .filter(|freq| **freq > 0.0)
+            .count();
+        if n_observed < 2 {
+            continue;
+        }
+        sum += var.effective_num_alleles();
+        n_sites += 1;
+    }
+    Ok(sum / n_sites as f64)
+}
+
+/// Observed and expected heterozygosity (Ho, He) of a site for the given samples.
+///
+/// Both are NaN when no sample in the set is called.
+pub(crate) fn observed_and_expected_het(var: &Variant, samples: &[usize]) -> (f64, f64) {
+    let mut n_called = 0;
+    let mut n_het = 0;
+    for sample_idx in samples {
+        let gt = &var.gts[*sample_idx];
+        if genotype_is_called(gt) {
+            n_called += 1;
+            if genotype_is_het(gt) {
+                n_het += 1;
+            }
+        }
+    }
+    let freqs = population_allele_freqs(var, samples);
+    if n_called == 0 || freqs.is_empty() {
+        return (f64::NAN, f64::NAN);
+    }
+    let ho = n_het as f64 / n_called as f64;
+    let he = 1.0 - freqs.iter().map(|freq| freq * freq).sum::<f64>();
+    (ho, he)
+}
+
+/// Inbreeding coefficient of a site, `1 - Ho/He`, for the given samples.
+///
+/// Monomorphic sites (He = 0) and sites with no called samples return NaN.
+pub fn fis(var: &Variant, samples: &[usize]) -> f64 {
+    let (ho, he) = observed_and_expected_het(var, samples);
+    if he == 0.0 {
+        return f64::NAN;
+    }
+    1.0 - ho / he
+}
+
+/// Inbreeding coefficient over all the variants, `1 - sum(Ho)/sum(He)`.
+///
+/// Sites with no called samples are ignored; monomorphic sites only add to
+/// the sums, so NaN is returned only if every site is monomorphic or missing.
+pub fn global_fis(vars: &mut Variants, samples: &[usize]) -> Result<f64, VCFParseError> {
+    let mut sum_ho = 0.0;
+    let mut sum_he = 0.0;
+    for var in vars.vars_iter.by_ref() {
+        let (ho, he) = observed_and_expected_het(&var?, samples);
+        if he.is_nan() {
+            continue;
+        }
+        sum_ho += ho;
+        sum_he += he;
+    }
+    if sum_he == 0.0 {
+        return Ok(f64::NAN);
+    }
+    Ok(1.0 - sum_ho / sum_he)
+}
+
+/// Number of alleles, summed over sites, found in each population and in no other.
+///
+/// Sites in which any population has no called allele are skipped, so that
+/// missing data cannot make an allele look private.
+pub fn private_alleles(
+    vars: &mut Variants,
+    pops: &[Vec<usize>],
+) -> Result<Vec<u64>, VCFParseError> {
+    let mut n_private = vec![0; pops.len()];
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        let counts: Vec<Vec<u64>> = pops
+            .iter()
+            .map(|pop| population_allele_counts(&var, pop))
+            .collect();
+        if counts.iter().any(|c| c.iter().sum::<u64>() == 0) {
+            continue;
+        }
+        let n_alleles = counts.iter().map(|c| c.len()).max().unwrap_or(0);
+        for allele in 0..n_alleles {
+            let mut pops_with_allele = counts
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| c.get(allele).copied().unwrap_or(0) > 0)
+                .map(|(pop_idx, _)| pop_idx);
+            if let (Some(pop_idx), None) = (pops_with_allele.next(), pops_with_allele.next()) {
+                n_private[pop_idx] += 1;
+            }
+        }
+    }
+    Ok(n_private)
+}
+
+/// Expected number of alleles in a random subsample of `g` gene copies.
+fn rarefied_richness(counts: &[u64], g: usize) -> f64 {
+    let n_total: u64 = counts.iter().sum();
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            // C(N - Ni, g) / C(N, g), as a product to avoid overflowing.
+            let n_without = n_total - count;
+            let mut prob_absent = 1.0;
+            for k in 0..g as u64 {
+                if n_without < g as u64 {
+                    prob_absent = 0.0;
+                    break;
+                }
+                prob_absent *= (n_without - k) as f64 / (n_total - k) as f64;
+            }
+            1.0 - prob_absent
+        })
+        .sum()
+}
+
+/// Allelic richness rarefied to `g` gene copies (El Mousadik & Petit, 1996).
+///
+/// For every population the richness is averaged over the sites in which it
+/// has at least `g` called alleles. Populations with no such site get NaN.
+/// gVCF reference blocks are not sites and are skipped.
+pub fn allelic_richness(
+    vars: &mut Variants,
+    pops: &[Vec<usize>],
+    g: usize,
+) -> Result<Vec<f64>, VCFParseError> {
+    let mut richness_sums = vec![0.0; pops.len()];
+    let mut n_sites = vec![0; pops.len()];
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        if var.is_reference_block() {
+            continue;
+        }
+        for (pop_idx, pop) in pops.iter().enumerate() {
+            let counts = population_allele_counts(&var, pop);
+            if counts.iter().sum::<u64>() < g as u64 {
+                continue;
+            }
+            richness_sums[pop_idx] += rarefied_richness(&counts, g);
+            n_sites[pop_idx] += 1;
+        }
+    }
+    Ok(richness_sums
+        .iter()
+        .zip(n_sites)
+        .map(|(sum, n_sites)| sum / n_sites as f64)
+        .collect())
+}
+
+/// Numerator and denominator of the Weir & Cockerham (1984) Fst of a site,
+/// the variance components `a` and `a + b + c` summed over alleles.
+///
+/// Only the samples with a called genotype count, the observed heterozygosity
+/// assumes diploids. None if there are less than two populations or any of
+/// them has less than two called samples.
+pub(crate) fn weir_cockerham_components(var: &Variant, pops: &[Vec<usize>]) -> Option<(f64, f64)> {
+    let n_pops = pops.len() as f64;
+    if pops.len() < 2 {
+        return None;
+    }
+    let called_gts: Vec<Vec<&Vec<AlleleInt>>> = pops
+        .iter()
+        .map(|pop| {
+            pop.iter()
+                .map(|sample_idx| &var.gts[*sample_idx])
+                .filter(|gt| genotype_is_called(gt))
+                .collect()
+        })
+        .collect();
+    let sizes: Vec<f64> = called_gts.iter().map(|gts| gts.len() as f64).collect();
+    if sizes.iter().any(|size| *size < 2.0) {
+        return None;
+    }
+    let n_total: f64 = sizes.iter().sum();
+    let n_mean = n_total / n_pops;
+    let n_c =
+        (n_total - sizes.iter().map(|size| size * size).sum::<f64>() / n_total) / (n_pops - 1.0);
+
+    let mut numerator = 0.0;
+    let mut denominator = 0.0;
+    for allele in 0..var.alleles.len() as AlleleInt {
+        let mut freqs = Vec::with_capacity(pops.len());
+        let mut hets = Vec::with_capacity(pops.len());
+        for gts in &called_gts {
+            let n_copies: usize = gts.iter().map(|gt| gt.len()).sum();
+            let n_allele = gts
+                .iter()
+                .flat_map(|gt| gt.iter())
+                .filter(|a| **a == allele)
+                .count();
+            let n_het = gts
+                .iter()
+                .filter(|gt| gt.contains(&allele) && gt.iter().any(|a| *a != allele))
+                .count();
+            freqs.push(n_allele as f64 / n_copies as f64);
+            hets.push(n_het as f64 / gts.len() as f64);
+        }
+        let p_mean = sizes.iter().zip(&freqs).map(|(n, p)| n * p).sum::<f64>() / n_total;
+        let s2 = sizes
+            .iter()
+            .zip(&freqs)
+            .map(|(n, p)| n * (p - p_mean).powi(2))
+            .sum::<f64>()
+            / ((n_pops - 1.0) * n_mean);
+        let h_mean = sizes.iter().zip(&hets).map(|(n, h)| n * h).sum::<f64>() / n_total;
+        let pq = p_mean * (1.0 - p_mean);
+
+        let a = n_mean / n_c
+            * (s2 - (pq - (n_pops - 1.0) / n_pops * s2 - h_mean / 4.0) / (n_mean - 1.0));
+        let b = n_mean / (n_mean - 1.0)
+            * (pq - (n_pops - 1.0) / n_pops * s2 - (2.0 * n_mean - 1.0) / (4.0 * n_mean) * h_mean);
+        let c = h_mean / 2.0;
+        numerator += a;
+        denominator += a + b + c;
+    }
+    Some((numerator, denominator))
+}
+
+/// Weir & Cockerham (1984) Fst over all the variants, as the ratio of the
+/// summed variance components.
+///
+/// Sites without two populations with two called samples are skipped, NaN is
+/// returned if no site is left.
+pub fn weir_cockerham_fst(vars: &mut Variants, pops: &[Vec<usize>]) -> Result<f64, VCFParseError> {
+    let mut numerator = 0.0;
+    let mut denominator = 0.0;
+    for var in vars.vars_iter.by_ref() {
+        if let Some((num, den)) = weir_cockerham_components(&var?, pops) {
+            numerator += num;
+            denominator += den;
+        }
+    }
+    Ok(numerator / denominator)
+}
+
+/// Within population (Hs) and total (Ht) expected heterozygosities of a site
+/// with the small sample corrections of Nei & Chesser (1983), as in Jost
+/// (2008): `Hs' = n/(n-1) Hs` and `Ht' = Ht + Hs'/(k n)`, `n` being the
+/// harmonic mean of the called alleles of the `k` populations.
+///
+/// None if there are less than two populations or any of them has less than
+/// two called alleles.
+pub(crate) fn corrected_hs_ht(var: &Variant, pops: &[Vec<usize>]) -> Option<(f64, f64)> {
+    let n_pops = pops.len() as f64;
+    if pops.len() < 2 {
+        return None;
+    }
+    let mut sizes = Vec::with_capacity(pops.len());
+    let mut pop_freqs = Vec::with_capacity(pops.len());
+    for pop in pops {
+        let counts = population_allele_counts(var, pop);
+        let n_called: u64 = counts.iter().sum();
+        if n_called < 2 {
+            return None;
+        }
+        sizes.push(n_called as f64);
+        pop_freqs.push(population_allele_freqs(var, pop));
+    }
+    let n_harmonic = n_pops / sizes.iter().map(|size| 1.0 / size).sum::<f64>();
+    let hs = pop_freqs
+        .iter()
+        .map(|freqs| 1.0 - freqs.iter().map(|p| p * p).sum::<f64>())
+        .sum::<f64>()
+        / n_pops;
+    let n_alleles = pop_freqs.iter().map(Vec::len).max().unwrap_or(0);
+    let mean_freqs: Vec<f64> = (0..n_alleles)
+        .map(|allele| {
+            pop_freqs
+                .iter()
+                .filter_map(|freqs| freqs.get(allele))
+                .sum::<f64>()
+                / n_pops
+        })
+        .collect();
+    let ht = 1.0 - mean_freqs.iter().map(|p| p * p).sum::<f64>();
+    let hs = n_harmonic / (n_harmonic - 1.0) * hs;
+    let ht = ht + hs / (n_pops * n_harmonic);
+    Some((hs, ht))
+}
+
+/// Differentiation statistics computed from the same corrected heterozygosities.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DifferentiationStats {
+    /// Within population heterozygosity, averaged over sites.
+    pub hs: f64,
+    /// Total heterozygosity, averaged over sites.
+    pub ht: f64,
+    /// Jost's (2008) D, `(Ht - Hs) / (1 - Hs) * k / (k - 1)`.
+    pub jost_d: f64,
+    /// Nei's (1973) Gst, `(Ht - Hs) / Ht`.
+    pub gst: f64,
+    /// Hedrick's (2005) standardized G'st, `Gst (k - 1 + Hs) / ((k - 1) (1 - Hs))`.
+    pub gst_hedrick: f64,
+}
+
+/// Hs, Ht, Jost's D, Gst and G'st of `k` populations over all the variants,
+/// in one pass, from the corrected heterozygosities averaged over sites.
+///
+/// D measures allelic differentiation, not fixation: with two alleles the
+/// within population heterozygosity can not be high, so, unlike with
+/// microsatellites, D of biallelic SNPs is small unless the populations are
+/// close to fixed for different alleles, and the small sample correction can
+/// make it negative. Sites without two populations with two called alleles
+/// are skipped, all the statistics are NaN if no site is left.
+pub fn differentiation_stats(
+    vars: &mut Variants,
+    pops: &[Vec<usize>],
+) -> Result<DifferentiationStats, VCFParseError> {
+    let mut hs_sum = 0.0;
+    let mut ht_sum = 0.0;
+    let mut n_sites = 0;
+    for var in vars.vars_iter.by_ref() {
+        if let Some((hs, ht)) = corrected_hs_ht(&var?, pops) {
+            hs_sum += hs;
+            ht_sum += ht;
+            n_sites += 1;
+        }
+    }
+    let (hs, ht) = (hs_sum / n_sites as f64, ht_sum / n_sites as f64);
+    let n_pops = pops.len() as f64;
+    let gst = (ht - hs) / ht;
+    Ok(DifferentiationStats {
+        hs,
+        ht,
+        jost_d: (ht - hs) / (1.0 - hs) * n_pops / (n_pops - 1.0),
+        gst,
+        gst_hedrick: gst * (n_pops - 1.0 + hs) / ((n_pops - 1.0) * (1.0 - hs)),
+    })
+}
+
+/// Jost's (2008) D over all the variants, see `differentiation_stats`.
+pub fn josts_d(vars: &mut Variants, pops: &[Vec<usize>]) -> Result<f64, VCFParseError> {
+    Ok(differentiation_stats(vars, pops)?.jost_d)
+}
+
+/// Nei's (1973) Gst over all the variants, see `differentiation_stats`.
+pub fn gst(vars: &mut Variants, pops: &[Vec<usize>]) -> Result<f64, VCFParseError> {
+    Ok(differentiation_stats(vars, pops)?.gst)
+}
+
+/// Hedrick's (2005) standardized G'st over all the variants, see
+/// `differentiation_stats`.
+pub fn gst_hedrick(vars: &mut Variants, pops: &[Vec<usize>]) -> Result<f64, VCFParseError> {
+    Ok(differentiation_stats(vars, pops)?.gst_hedrick)
+}
+
+/// Numerator and denominator of the Hudson Fst of a site, as defined by
+/// Bhatia et al. (2013): the between population diversity minus the mean
+/// unbiased within population diversity, and the between population
+/// diversity, summed over alleles.
+///
+/// Counts called alleles, not samples. None if any population has less than
+/// two called alleles.
+pub(crate) fn hudson_components(
+    var: &Variant,
+    pop_a: &[usize],
+    pop_b: &[usize],
+) -> Option<(f64, f64)> {
+    let counts_a = population_allele_counts(var, pop_a);
+    let counts_b = population_allele_counts(var, pop_b);
+    let n_a: u64 = counts_a.iter().sum();
+    let n_b: u64 = counts_b.iter().sum();
+    if n_a < 2 || n_b < 2 {
+        return None;
+    }
+    let freqs_a = population_allele_freqs(var, pop_a);
+    let freqs_b = population_allele_freqs(var, pop_b);
+    let within = |freqs: &[f64], n: u64| {
+        n as f64 / (n - 1) as f64 * (1.0 - freqs.iter().map(|p| p * p).sum::<f64>())
+    };
+    let between = 1.0
+        - freqs_a
+            .iter()
+            .zip(&freqs_b)
+            .map(|(p_a, p_b)| p_a * p_b)
+            .sum::<f64>();
+    let numerator = between - (within(&freqs_a, n_a) + within(&freqs_b, n_b)) / 2.0;
+    Some((numerator, between))
+}
+
+/// The Hudson Fst components of a site, see `hudson_fst`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HudsonSite {
+    pub chrom: String,
+    pub pos: u64,
+    pub numerator: f64,
+    pub denominator: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HudsonFst {
+    pub fst: f64,
+    /// The components of every site used, when asked for, to sum them by
+    /// windows.
+    pub sites: Option<Vec<HudsonSite>>,
+}
+
+/// Hudson Fst between two populations, with the "ratio of averages" of
+/// Bhatia et al. (2013), which is not biased by unequal sample sizes.
+///
+/// Sites with less than two called alleles in a population are skipped, NaN is
+/// returned if no site is left.
+pub fn hudson_fst(
+    vars: &mut Variants,
+    pop_a: &[usize],
+    pop_b: &[usize],
+    keep_sites: bool,
+) -> Result<HudsonFst, VCFParseError> {
+    let mut numerator = 0.0;
+    let mut denominator = 0.0;
+    let mut sites = Vec::new();
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        if let Some((num, den)) = hudson_components(&var, pop_a, pop_b) {
+            numerator += num;
+            denominator += den;
+            if keep_sites {
+                sites.push(HudsonSite {
+                    chrom: var.chrom.clone(),
+                    pos: var.pos,
+                    numerator: num,
+                    denominator: den,
+                });
+            }
+        }
+    }
+    Ok(HudsonFst {
+        fst: numerator / denominator,
+        sites: keep_sites.then_some(sites),
+    })
+}
+
+/// Joint site frequency spectrum of two populations, the input of ∂a∂i and
+/// moments: entry `[i, j]` is the number of biallelic sites with `i` ALT
+/// alleles in `pop_a` and `j` in `pop_b`.
+///
+/// The shape is `(ploidy * pop_a.len() + 1, ploidy * pop_b.len() + 1)`. ALT is
+/// taken as the derived allele, see `Variants::polarize_from_aa`. Folded, the
+/// counts are those of the allele that is minor in both populations together.
+/// Sites with any missing allele in the populations are dropped, they are
+/// not projected.
+pub fn joint_sfs(
+    vars: &mut Variants,
+    pop_a: &[usize],
+    pop_b: &[usize],
+    folded: bool,
+) -> Result<Array2<u64>, VCFParseError> {
+    let ploidy = vars.ploidy as usize;
+    let (n_a, n_b) = (ploidy * pop_a.len(), ploidy * pop_b.len());
+    let mut sfs = Array2::zeros((n_a + 1, n_b + 1));
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        if var.alleles.len() != 2 {
+            continue;
+        }
+        let counts_a = population_allele_counts(&var, pop_a);
+        let counts_b = population_allele_counts(&var, pop_b);
+        if counts_a.iter().sum::<u64>() != n_a as u64 || counts_b.iter().sum::<u64>() != n_b as u64
+        {
+            continue;
+        }
+        let (mut alt_a, mut alt_b) = (counts_a[1] as usize, counts_b[1] as usize);
+        if folded && 2 * (alt_a + alt_b) > n_a + n_b {
+            (alt_a, alt_b) = (n_a - alt_a, n_b - alt_b);
+        }
+        sfs[[alt_a, alt_b]] += 1;
+    }
+    Ok(sfs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{parse_vcf_buffer_str, parse_vcf_str, VCF_45, VCF_MISSING};
+    use crate::VcfReaderBuilder;
+
+    const VCF_FIS: &str = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2\tS3\tS4
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT\t0/0\t0/0\t0/1\t1/1
+1\t20\t.\tA\tG\t.\tPASS\t.\tGT\t0/0\t0/0\t0/0\t0/0";
+
+    #[test]
+    fn site_fis() {
+        let mut vars = parse_vcf_buffer_str(VCF_FIS);
+        let var = vars.vars_iter.next().unwrap().unwrap();
+        let samples = [0, 1, 2, 3];
+        assert!((fis(&var, &samples) - 7.0 / 15.0).abs() < 1e-12);
+
+        let monomorphic = vars.vars_iter.next().unwrap().unwrap();
+        assert!(fis(&monomorphic, &samples).is_nan());
+    }
+
+    #[test]
+    fn whole_file_fis() {
+        let mut vars = parse_vcf_buffer_str(VCF_FIS);
+        let fis = global_fis(&mut vars, &[0, 1, 2, 3]).unwrap();
+        assert!((fis - 7.0 / 15.0).abs() < 1e-12);
+    }
+
+    const VCF_TWO_POPS: &str = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA1\tA2\tB1\tB2
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT\t0/0\t0/1\t0/0\t0/0
+1\t20\t.\tA\tG,T\t.\tPASS\t.\tGT\t1/1\t1/1\t0/2\t2/2
+1\t30\t.\tA\tG\t.\tPASS\t.\tGT\t0/0\t./.\t0/1\t./.
+1\t40\t.\tA\tG\t.\tPASS\t.\tGT\t./.\t./.\t0/1\t1/1";
+
+    #[test]
+    fn two_pop_private_alleles() {
+        let mut vars = parse_vcf_buffer_str(VCF_TWO_POPS);
+        let pops = vec![vec![0, 1], vec![2, 3]];
+        let n_private = private_alleles(&mut vars, &pops).unwrap();
+        assert_eq!(n_private, vec![2, 3]);
+    }
+
+    #[test]
+    fn rarefaction() {
+        assert!((rarefied_richness(&[3, 1], 2) - 1.5).abs() < 1e-12);
+        assert!((rarefied_richness(&[3, 1], 4) - 2.0).abs() < 1e-12);
+        assert!((rarefied_richness(&[2, 2, 0], 1) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn pop_allelic_richness() {
+        let mut vars = parse_vcf_buffer_str(VCF_TWO_POPS);
+        let pops = vec![vec![0, 1], vec![2, 3]];
+        let richness = allelic_richness(&mut vars, &pops, 4).unwrap();
+        // Pop A: sites 10 (2 alleles) and 20 (1 allele), B: sites 10, 20 and 40.
+        assert!((richness[0] - 1.5).abs() < 1e-12);
+        assert!((richness[1] - 5.0 / 3.0).abs() < 1e-12);
+
+        let gvcf =
+            format!("{VCF_TWO_POPS}\n1\t41\t.\tA\t<NON_REF>\t.\t.\tEND=90\tGT\t0/0\t0/0\t0/0\t0/0");
+        let mut vars = parse_vcf_buffer_str(&gvcf);
+        let richness = allelic_richness(&mut vars, &pops, 4).unwrap();
+        assert!((richness[0] - 1.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn diversity_and_pi() {
+        let vars = parse_vcf_str(VCF_FIS);
+        let samples = [0, 1, 2, 3];
+        assert!((nei_diversity(&vars[0], &samples) - 8.0 / 7.0 * 30.0 / 64.0).abs() < 1e-12);
+        assert_eq!(nei_diversity(&vars[1], &samples), 0.0);
+
+        let mut vars = parse_vcf_buffer_str(VCF_FIS);
+        let pi = pi(&mut vars, &samples).unwrap();
+        assert!((pi - 15.0 / 28.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn chromosome_pi() {
+        let samples = [0, 1, 2];
+        let total_pi = pi(&mut parse_vcf_buffer_str(VCF_45), &samples).unwrap();
+        let contig_lengths = HashMap::from([("20".to_string(), 62435964)]);
+        let pis = pi_per_chromosome(&mut parse_vcf_buffer_str(VCF_45), &samples, &contig_lengths)
+            .unwrap();
+        assert_eq!(pis.len(), 1);
+        assert_eq!(pis[0].0, "20");
+        assert!((pis[0].1 - total_pi / 62435964.0).abs() < 1e-18);
+
+        // 21 has no contig length, so its pi is not divided.
+        let vcf = VCF_45.replace("\n20\t1234567\t", "\n21\t1234567\t");
+        let pis =
+            pi_per_chromosome(&mut parse_vcf_buffer_str(&vcf), &samples, &contig_lengths).unwrap();
+        let microsat = nei_diversity(&parse_vcf_str(VCF_45)[5], &samples);
+        assert_eq!(pis[1], ("21".to_string(), 2.0 * microsat));
+    }
+
+    const VCF_FST: &str = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA1\tA2\tB1\tB2
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT\t0/0\t0/0\t1/1\t1/1
+1\t150\t.\tA\tG\t.\tPASS\t.\tGT\t0/1\t0/1\t0/1\t0/1";
+
+    #[test]
+    fn weir_cockerham() {
+        let vars = parse_vcf_str(VCF_FST);
+        let pops = vec![vec![0, 1], vec![2, 3]];
+        assert_eq!(weir_cockerham_components(&vars[0], &pops), Some((1.0, 1.0)));
+        let (num, den) = weir_cockerham_components(&vars[1], &pops).unwrap();
+        assert!(num.abs() < 1e-12);
+        assert!((den - 0.5).abs() < 1e-12);
+        assert_eq!(weir_cockerham_components(&vars[0], &pops[..1]), None);
+
+        let mut vars = parse_vcf_buffer_str(VCF_FST);
+        let fst = weir_cockerham_fst(&mut vars, &pops).unwrap();
+        assert!((fst - 2.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn jost() {
+        let vars = parse_vcf_str(VCF_FST);
+        let pops = vec![vec![0, 1], vec![2, 3]];
+        // Fixed for different alleles: Hs = 0 and Ht = 0.5.
+        assert_eq!(corrected_hs_ht(&vars[0], &pops), Some((0.0, 0.5)));
+        // Hs = Ht = 0.5, corrected with 4 alleles per population.
+        let (hs, ht) = corrected_hs_ht(&vars[1], &pops).unwrap();
+        assert!((hs - 2.0 / 3.0).abs() < 1e-12);
+        assert!((ht - 7.0 / 12.0).abs() < 1e-12);
+        assert_eq!(corrected_hs_ht(&vars[0], &pops[..1]), None);
+
+        let d = josts_d(&mut parse_vcf_buffer_str(VCF_FST), &pops).unwrap();
+        // Mean Hs = 1/3 and Ht = 13/24.
+        assert!((d - 5.0 / 8.0).abs() < 1e-12);
+
+        let fixed = VCF_FST.replace("0/1\t0/1\t0/1\t0/1", "0/0\t0/0\t1/1\t1/1");
+        let d = josts_d(&mut parse_vcf_buffer_str(&fixed), &pops).unwrap();
+        assert!((d - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn differentiation() {
+        let pops = vec![vec![0, 1], vec![2, 3]];
+        let stats = differentiation_stats(&mut parse_vcf_buffer_str(VCF_FST), &pops).unwrap();
+        assert!((stats.hs - 1.0 / 3.0).abs() < 1e-12);
+        assert!((stats.ht - 13.0 / 24.0).abs() < 1e-12);
+        assert!((stats.jost_d - 5.0 / 8.0).abs() < 1e-12);
+        assert!((stats.gst - 5.0 / 13.0).abs() < 1e-12);
+        assert!((stats.gst_hedrick - 10.0 / 13.0).abs() < 1e-12);
+
+        let gst = gst(&mut parse_vcf_buffer_str(VCF_FST), &pops).unwrap();
+        assert_eq!(gst, stats.gst);
+        let gst_hedrick = gst_hedrick(&mut parse_vcf_buffer_str(VCF_FST), &pops).unwrap();
+        assert_eq!(gst_hedrick, stats.gst_hedrick);
+    }
+
+    #[test]
+    fn hudson() {
+        let vars = parse_vcf_str(VCF_FST);
+        let (pop_a, pop_b) = ([0, 1], [2, 3]);
+        assert_eq!(
+            hudson_components(&vars[0], &pop_a, &pop_b),
+            Some((1.0, 1.0))
+        );
+        // Hb = 0.5 and Hw = 4/3 * 0.5 in both populations.
+        let (num, den) = hudson_components(&vars[1], &pop_a, &pop_b).unwrap();
+        assert!((num + 1.0 / 6.0).abs() < 1e-12);
+        assert!((den - 0.5).abs() < 1e-12);
+        assert_eq!(hudson_components(&vars[0], &pop_a, &[2]), Some((1.0, 1.0)));
+        assert_eq!(hudson_components(&vars[0], &pop_a, &[]), None);
+
+        let hudson = hudson_fst(&mut parse_vcf_buffer_str(VCF_FST), &pop_a, &pop_b, false).unwrap();
+        assert!((hudson.fst - 5.0 / 9.0).abs() < 1e-12);
+        assert_eq!(hudson.sites, None);
+
+        let hudson = hudson_fst(&mut parse_vcf_buffer_str(VCF_FST), &pop_a, &pop_b, true).unwrap();
+        let sites = hudson.sites.unwrap();
+        assert_eq!(sites.len(), 2);
+        assert_eq!((sites[1].chrom.as_str(), sites[1].pos), ("1", 150));
+    }
+
+    #[test]
+    fn two_pop_sfs() {
+        let vcf = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA1\tA2\tB1\tB2
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT\t0/0\t0/1\t0/0\t0/0
+1\t20\t.\tA\tG\t.\tPASS\t.\tGT\t1/1\t1/1\t0/1\t1/1
+1\t30\t.\tA\tG\t.\tPASS\t.\tGT\t0/1\t./.\t0/1\t0/0
+1\t40\t.\tA\tG,T\t.\tPASS\t.\tGT\t0/1\t0/2\t0/1\t0/0
+1\t50\t.\tA\tG\t.\tPASS\t.\tGT\t0/1\t0/1\t0/1\t0/1";
+        let (pop_a, pop_b) = ([0, 1], [2, 3]);
+        let sfs = joint_sfs(&mut parse_vcf_buffer_str(vcf), &pop_a, &pop_b, false).unwrap();
+        assert_eq!(sfs.dim(), (5, 5));
+        assert_eq!(sfs.sum(), 3);
+        assert_eq!((sfs[[1, 0]], sfs[[4, 3]], sfs[[2, 2]]), (1, 1, 1));
+
+        let sfs = joint_sfs(&mut parse_vcf_buffer_str(vcf), &pop_a, &pop_b, true).unwrap();
+        assert_eq!(sfs.sum(), 3);
+        assert_eq!((sfs[[1, 0]], sfs[[0, 1]], sfs[[2, 2]]), (1, 1, 1));
+
+        let sfs = joint_sfs(&mut parse_vcf_buffer_str(vcf), &pop_a, &[2], false).unwrap();
+        assert_eq!(sfs.dim(), (5, 3));
+        assert_eq!((sfs[[4, 1]], sfs[[2, 1]]), (1, 1));
+    }
+
+    #[test]
+    fn mean_effective_alleles() {
+        let mut vars = parse_vcf_buffer_str(VCF_45);
+        let mean = mean_effective_num_alleles(&mut vars).unwrap();
+        let expected = (2.0 + 18.0 / 13.0 + 1.8 + 2.0 * 18.0 / 7.0) / 5.0;
+        assert!((mean - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn spanning_deletion_frequencies() {
+        let vcf = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2\tS3
+1\t100\t.\tATG\tA\t.\tPASS\t.\tGT\t0/1\t1/1\t0/0
+1\t101\t.\tT\tC,*\t.\tPASS\t.\tGT\t0/2\t2/2\t0/1";
+        let vars = parse_vcf_str(vcf);
+        assert_eq!(
+            vars[1].allele_frequencies(),
+            vec![2.0 / 6.0, 1.0 / 6.0, 3.0 / 6.0]
+        );
+        let freqs = spanning_aware_af(&vars);
+        assert_eq!(freqs[0], vec![0.5, 0.5]);
+        // Only S3 is left, S1 and S2 carry a `*`.
+        assert_eq!(freqs[1], vec![0.5, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn vcf_45_site_types() {
+        let mut vars = parse_vcf_buffer_str(VCF_45);
+        let counts = site_type_counts(&mut vars).unwrap();
+        assert_eq!(
+            counts,
+            SiteCounts {
+                biallelic: 3,
+                multiallelic: 2,
+                monomorphic: 1,
+                indels: 2,
+                snps: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn mixed_ploidies() {
+        let vcf = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2\tS3
+X\t10\t.\tA\tG\t.\tPASS\t.\tGT\t0/1\t1\t.
+X\t20\t.\tA\tG\t.\tPASS\t.\tGT\t0|0\t0\t1/1";
+        let result = VcfReaderBuilder::new().read_buffer(vcf.as_bytes());
+        assert!(matches!(
+            result,
+            Err(VCFParseError::DifferentPloidiesError(_))
+        ));
+
+        let mut vars = VcfReaderBuilder::new()
+            .mixed_ploidy(true)
+            .read_buffer(vcf.as_bytes())
+            .unwrap();
+        let histogram = ploidy_histogram(&mut vars).unwrap();
+        assert_eq!(histogram, HashMap::from([(1, 3), (2, 3)]));
+    }
+
+    #[test]
+    fn sample_summaries() {
+        let counts = |vcf: &str| -> Vec<(u64, u64, u64, u64)> {
+            sample_report(&mut parse_vcf_buffer_str(vcf))
+                .unwrap()
+                .iter()
+                .map(|s| (s.n_called, s.n_missing, s.n_het, s.n_hom_alt))
+                .collect()
+        };
+        assert_eq!(
+            counts(VCF_45),
+            vec![(6, 0, 3, 0), (6, 0, 5, 0), (6, 0, 0, 4)]
+        );
+        assert_eq!(
+            counts(VCF_MISSING),
+            vec![(1, 1, 1, 0), (1, 1, 0, 0), (2, 0, 1, 1)]
+        );
+
+        let report = sample_report(&mut parse_vcf_buffer_str(VCF_MISSING)).unwrap();
+        assert_eq!(report[0].name, "S1");
+        assert_eq!((report[0].het_rate, report[0].missing_rate), (1.0, 0.5));
+        assert_eq!((report[2].het_rate, report[2].missing_rate), (0.5, 0.0));
+    }
+
+    #[test]
+    fn chrom_stats() {
+        let stats = per_chrom_stats(&mut parse_vcf_buffer_str(VCF_45)).unwrap();
+        assert_eq!(
+            stats,
+            vec![ChromStats {
+                chrom: "20".to_string(),
+                n_variants: 6,
+                mean_qual: 41.0,
+                ts_tv: 1.0,
+                mean_call_rate: 1.0,
+            }]
+        );
+
+        let vcf = VCF_MISSING.replace("\n20\t200\t", "\n21\t200\t");
+        let stats = per_chrom_stats(&mut parse_vcf_buffer_str(&vcf)).unwrap();
+        let chroms: Vec<&str> = stats.iter().map(|stats| stats.chrom.as_str()).collect();
+        assert_eq!(chroms, vec!["20", "21"]);
+        assert!(stats[0].mean_qual.is_nan());
+        assert_eq!(stats[0].ts_tv, f64::INFINITY);
+        assert!((stats[1].mean_call_rate - 2.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn info_stats() {
+        let stats = info_field_stats(&mut parse_vcf_buffer_str(VCF_45), "DP").unwrap();
+        assert_eq!((stats.count, stats.min, stats.max), (6, 9.0, 14.0));
+        assert!((stats.mean - 11.0).abs() < 1e-12);
+        assert!((stats.variance - 4.4).abs() < 1e-12);
+
+        // AF is a list in the multiallelic sites.
+        let stats = info_field_stats(&mut parse_vcf_buffer_str(VCF_45), "AF").unwrap();
+        assert_eq!(stats.count, 2);
+        let stats = info_field_stats(&mut parse_vcf_buffer_str(VCF_45), "MQ").unwrap();
+        assert_eq!(stats.count, 0);
+        assert!(stats.mean.is_nan() && stats.variance.is_nan());
+    }
+
+    #[test]
+    fn population_sharing() {
+        let vcf = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2\tS3\tS4
+1\t10\t.\tA\tG\t.\tPASS\t.\tGT\t0/1\t0/0\t0/1\t1/1
+1\t20\t.\tA\tG\t.\tPASS\t.\tGT\t0/0\t0/0\t1/1\t1/1
+1\t30\t.\tA\tG\t.\tPASS\t.\tGT\t0/1\t0/0\t0/0\t0/0
+1\t40\t.\tA\tG\t.\tPASS\t.\tGT\t0/0\t0/0\t0/1\t0/0
+1\t50\t.\tA\tG\t.\tPASS\t.\tGT\t0/0\t0/0\t0/0\t0/0
+1\t60\t.\tA\tG\t.\tPASS\t.\tGT\t./.\t./.\t0/1\t0/0";
+        let stats =
+            cross_population_sharing(&mut parse_vcf_buffer_str(vcf), &[0, 1], &[2, 3]).unwrap();
+        assert_eq!(
+            stats,
+            SharingStats {
+                n_sites: 5,
+                shared: 0.2,
+                fixed_differences: 0.2,
+                exclusive_a: 0.2,
+                exclusive_b: 0.2,
+            }
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use ndarray::Array2;
+
+/// A tree node: a leaf with a label, or an internal node with its children
+/// and the lengths of the branches that lead to them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewickTree {
+    pub label: Option<String>,
+    pub children: Vec<(NewickTree, f64)>,
+}
+
+impl NewickTree {
+    fn leaf(label: &str) -> NewickTree {
+        NewickTree {
+            label: Some(label.to_string()),
+            children: Vec::new(),
+        }
+    }
+
+    fn write_node(&self, newick: &mut String) {
+        if !self.children.is_empty() {
+            newick.push('(');
+            for (idx, (child, length)) in self.children.iter().enumerate() {
+                if idx > 0 {
+                    newick.push(',');
+                }
+                child.write_node(newick);
+                newick.push_str(&format!(":{length}"));
+            }
+            newick.push(')');
+        }
+        if let Some(label) = &self.label {
+            newick.push_str(label);
+        }
+    }
+
+    /// The tree in Newick format, terminated by `;`.
+    pub fn to_newick(&self) -> String {
+        let mut newick = String::new();
+        self.write_node(&mut newick);
+        newick.push(';');
+        newick
+    }
+}
+
+/// Unrooted tree built by neighbor-joining (Saitou and Nei 1987).
+///
+/// The last three nodes are joined to the root, and ties are resolved in
+/// favour of the first pair of nodes. Branch lengths are not clamped, so
+/// non-additive distances can give negative lengths.
+pub fn neighbor_joining(dist: &Array2<f64>, labels: &[String]) -> NewickTree {
+    let n_taxa = labels.len();
+    assert!(n_taxa > 0, "At least one taxon is required");
+    assert_eq!(
+        dist.dim(),
+        (n_taxa, n_taxa),
+        "There must be a distance for every pair of labels"
+    );
+
+    let mut nodes: Vec<NewickTree> = labels.iter().map(|label| NewickTree::leaf(label)).collect();
+    let mut dist: Vec<Vec<f64>> = dist.rows().into_iter().map(|row| row.to_vec()).collect();
+    while nodes.len() > 3 {
+        let n_nodes = nodes.len();
+        let sums: Vec<f64> = dist.iter().map(|row| row.iter().sum()).collect();
+        let mut pair = (0, 1);
+        let mut min_q = f64::INFINITY;
+        for i in 0..n_nodes {
+            for j in i + 1..n_nodes {
+                let q = (n_nodes - 2) as f64 * dist[i][j] - sums[i] - sums[j];
+                if q < min_q {
+                    min_q = q;
+                    pair = (i, j);
+                }
+            }
+        }
+
+        let (i, j) = pair;
+        let length_i = dist[i][j] / 2.0 + (sums[i] - sums[j]) / (2.0 * (n_nodes - 2) as f64);
+        let length_j = dist[i][j] - length_i;
+        let new_dists: Vec<f64> = (0..n_nodes)
+            .filter(|k| *k != i && *k != j)
+            .map(|k| (dist[i][k] + dist[j][k] - dist[i][j]) / 2.0)
+            .collect();
+
+        // j > i, so removing j first keeps i in place.
+        let node_j = nodes.remove(j);
+        let node_i = nodes.remove(i);
+        for idx in [j, i] {
+            dist.remove(idx);
+            for row in dist.iter_mut() {
+                row.remove(idx);
+            }
+        }
+        for (row, new_dist) in dist.iter_mut().zip(&new_dists) {
+            row.push(*new_dist);
+        }
+        let mut new_row = new_dists;
+        new_row.push(0.0);
+        dist.push(new_row);
+        nodes.push(NewickTree {
+            label: None,
+            children: vec![(node_i, length_i), (node_j, length_j)],
+        });
+    }
+
+    match nodes.len() {
+        1 => nodes.remove(0),
+        2 => {
+            let length = dist[0][1];
+            let node_b = nodes.remove(1);
+            let node_a = nodes.remove(0);
+            NewickTree {
+                label: None,
+                children: vec![(node_a, length / 2.0), (node_b, length / 2.0)],
+            }
+        }
+        _ => {
+            let (ab, ac, bc) = (dist[0][1], dist[0][2], dist[1][2]);
+            let lengths = [
+                (ab + ac - bc) / 2.0,
+                (ab + bc - ac) / 2.0,
+                (ac + bc - ab) / 2.0,
+            ];
+            NewickTree {
+                label: None,
+                children: nodes.into_iter().zip(lengths).collect(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    fn labels(labels: &[&str]) -> Vec<String> {
+        labels.iter().map(|label| label.to_string()).collect()
+    }
+
+    #[test]
+    fn additive_tree() {
+        // Additive distances of the unrooted tree ((a:2,b:3):3,c:4,(d:2,e:1):2),
+        // as in the usual worked example of the method. In the second step the
+        // pairs (c, (a, b)) and (d, e) tie, and the first one is joined.
+        let dist = array![
+            [0.0, 5.0, 9.0, 9.0, 8.0],
+            [5.0, 0.0, 10.0, 10.0, 9.0],
+            [9.0, 10.0, 0.0, 8.0, 7.0],
+            [9.0, 10.0, 8.0, 0.0, 3.0],
+            [8.0, 9.0, 7.0, 3.0, 0.0],
+        ];
+        let tree = neighbor_joining(&dist, &labels(&["a", "b", "c", "d", "e"]));
+        assert_eq!(tree.to_newick(), "(d:2,e:1,(c:4,(a:2,b:3):3):2);");
+    }
+
+    #[test]
+    fn few_taxa() {
+        let tree = neighbor_joining(&array![[0.0]], &labels(&["a"]));
+        assert_eq!(tree.to_newick(), "a;");
+        let tree = neighbor_joining(&array![[0.0, 3.0], [3.0, 0.0]], &labels(&["a", "b"]));
+        assert_eq!(tree.to_newick(), "(a:1.5,b:1.5);");
+        let dist = array![[0.0, 3.0, 4.0], [3.0, 0.0, 5.0], [4.0, 5.0, 0.0]];
+        let tree = neighbor_joining(&dist, &labels(&["a", "b", "c"]));
+        assert_eq!(tree.to_newick(), "(a:1,b:2,c:3);");
+    }
+}
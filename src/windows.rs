@@ -0,0 +1,408 @@
+use crate::annotation::IntervalTree;
+use crate::{VCFParseError, Variant, Variants, VariantsIter};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+/// A genomic window in BED coordinates: 0-based start and exclusive end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub chrom: String,
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Iterator over the non-empty fixed-size windows of a coordinate-sorted file.
+pub struct FixedWindows<'a> {
+    vars_iter: VariantsIter<'a>,
+    size: u64,
+    /// The last window and its variants that reach beyond it.
+    spanning: Option<(Window, Vec<Variant>)>,
+}
+
+impl Iterator for FixedWindows<'_> {
+    type Item = Result<(Window, Vec<Variant>), VCFParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (window, mut vars) = match self.spanning.take() {
+            Some((prev_window, spanning)) => {
+                let window = Window {
+                    chrom: prev_window.chrom,
+                    start: prev_window.end,
+                    end: prev_window.end + self.size,
+                };
+                (window, spanning)
+            }
+            None => {
+                let first_var = match self.vars_iter.next()? {
+                    Ok(var) => var,
+                    Err(e) => return Some(Err(e)),
+                };
+                let start = first_var.pos.saturating_sub(1) / self.size * self.size;
+                let window = Window {
+                    chrom: first_var.chrom.clone(),
+                    start,
+                    end: start + self.size,
+                };
+                (window, vec![first_var])
+            }
+        };
+        while let Some(Ok(var)) = self.vars_iter.peek() {
+            if var.chrom != window.chrom || var.pos > window.end {
+                break;
+            }
+            vars.push(self.vars_iter.next().unwrap().unwrap());
+        }
+        let spanning: Vec<Variant> = vars
+            .iter()
+            .filter(|var| var.end() > window.end)
+            .cloned()
+            .collect();
+        if !spanning.is_empty() {
+            self.spanning = Some((window.clone(), spanning));
+        }
+        Some(Ok((window, vars)))
+    }
+}
+
+/// Groups the variants in windows of `size` bp aligned to multiples of `size`.
+///
+/// A variant is in every window that its reference span, from POS to
+/// `Variant::end`, overlaps, so long deletions are cloned into the following
+/// windows. The input must be sorted; windows without variants are not
+/// yielded.
+pub fn fixed_windows(vars: Variants, size: u64) -> FixedWindows {
+    assert!(size > 0, "Window size must be positive");
+    FixedWindows {
+        vars_iter: vars.vars_iter,
+        size,
+        spanning: None,
+    }
+}
+
+/// Keeps the variants whose reference span, from POS to `Variant::end`,
+/// overlaps any of the regions of a BED file.
+///
+/// All the regions are loaded in memory; `track`, `browser` and `#` lines
+/// are skipped.
+pub fn filter_by_bed<'a>(
+    vars: Variants<'a>,
+    bed: &PathBuf,
+) -> Result<Variants<'a>, Box<dyn Error>> {
+    let mut regions: HashMap<String, Vec<(u64, u64, ())>> = HashMap::new();
+    for (line_idx, line) in BufReader::new(File::open(bed)?).lines().enumerate() {
+        let line = line?;
+        if line.is_empty()
+            || ["#", "track", "browser"]
+                .iter()
+                .any(|prefix| line.starts_with(prefix))
+        {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        let (start, end) = match (
+            fields.get(1).and_then(|start| start.parse::<u64>().ok()),
+            fields.get(2).and_then(|end| end.parse::<u64>().ok()),
+        ) {
+            (Some(start), Some(end)) if start < end => (start, end),
+            _ => return Err(format!("Invalid BED line {}", line_idx + 1).into()),
+        };
+        regions
+            .entry(fields[0].to_string())
+            .or_default()
+            .push((start + 1, end, ()));
+    }
+    let regions: HashMap<String, IntervalTree<()>> = regions
+        .into_iter()
+        .map(|(chrom, regions)| (chrom, IntervalTree::new(regions)))
+        .collect();
+    Ok(vars.filter_variants(move |var| {
+        regions
+            .get(&var.chrom)
+            .is_some_and(|tree| !tree.overlapping(var.pos, var.end()).is_empty())
+    }))
+}
+
+/// Distance in bp from every variant to the previous one of the same chromosome.
+///
+/// The input must be sorted; the first variant of every chromosome yields nothing.
+pub fn inter_variant_distances<'a>(
+    vars: Variants<'a>,
+) -> impl Iterator<Item = Result<(String, u64), VCFParseError>> + 'a {
+    let mut prev: Option<(String, u64)> = None;
+    vars.vars_iter.filter_map(move |var| {
+        let var = match var {
+            Ok(var) => var,
+            Err(e) => return Some(Err(e)),
+        };
+        let distance = match &prev {
+            Some((chrom, pos)) if *chrom == var.chrom => {
+                Some(Ok((var.chrom.clone(), var.pos.saturating_sub(*pos))))
+            }
+            _ => None,
+        };
+        prev = Some((var.chrom, var.pos));
+        distance
+    })
+}
+
+/// Iterator over the variants with their flanking variants, see `with_neighbors`.
+pub struct Neighbors<'a> {
+    vars_iter: VariantsIter<'a>,
+    k: usize,
+    upstream: VecDeque<Variant>,
+    downstream: VecDeque<Variant>,
+}
+
+impl Iterator for Neighbors<'_> {
+    type Item = Result<(Vec<Variant>, Variant, Vec<Variant>), VCFParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let var = match self.downstream.pop_front() {
+            Some(var) => var,
+            None => {
+                self.upstream.clear();
+                match self.vars_iter.next()? {
+                    Ok(var) => var,
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+        };
+        if self
+            .upstream
+            .back()
+            .is_some_and(|prev| prev.chrom != var.chrom)
+        {
+            self.upstream.clear();
+        }
+        while self.downstream.len() < self.k {
+            match self.vars_iter.peek() {
+                Some(Ok(next_var)) if next_var.chrom == var.chrom => {
+                    self.downstream
+                        .push_back(self.vars_iter.next().unwrap().unwrap());
+                }
+                _ => break,
+            }
+        }
+
+        let item = (
+            self.upstream.iter().cloned().collect(),
+            var.clone(),
+            self.downstream.iter().cloned().collect(),
+        );
+        self.upstream.push_back(var);
+        if self.upstream.len() > self.k {
+            self.upstream.pop_front();
+        }
+        Some(Ok(item))
+    }
+}
+
+/// Yields every variant with the `k` previous and the `k` next variants of
+/// the same chromosome, both in file order.
+///
+/// The input must be sorted. At most 2k+1 variants are kept in memory, and
+/// the neighbours are cloned for every variant.
+pub fn with_neighbors(vars: Variants, k: usize) -> Neighbors {
+    Neighbors {
+        vars_iter: vars.vars_iter,
+        k,
+        upstream: VecDeque::with_capacity(k + 1),
+        downstream: VecDeque::with_capacity(k),
+    }
+}
+
+/// Fraction of the length of the contigs covered by the reference span of
+/// the variants, from POS to `Variant::span_end`, merging the overlaps.
+///
+/// Variants in contigs not found in `contig_lengths`, or past their end, are
+/// ignored. The input does not need to be sorted, but all the spans are kept
+/// in memory.
+pub fn variant_span_fraction(
+    vars: &mut Variants,
+    contig_lengths: &HashMap<String, u64>,
+) -> Result<f64, VCFParseError> {
+    let mut spans: HashMap<String, Vec<(u64, u64)>> = HashMap::new();
+    for var in vars.vars_iter.by_ref() {
+        let var = var?;
+        if let Some(length) = contig_lengths
+            .get(&var.chrom)
+            .filter(|length| var.pos <= **length)
+        {
+            let span = (var.pos, var.span_end().min(*length));
+            spans.entry(var.chrom).or_default().push(span);
+        }
+    }
+
+    let mut covered = 0;
+    for chrom_spans in spans.values_mut() {
+        chrom_spans.sort_unstable();
+        let mut merged: Option<(u64, u64)> = None;
+        for (start, end) in chrom_spans.iter().copied() {
+            merged = match merged {
+                Some((merged_start, merged_end)) if start <= merged_end + 1 => {
+                    Some((merged_start, merged_end.max(end)))
+                }
+                Some((merged_start, merged_end)) => {
+                    covered += merged_end + 1 - merged_start;
+                    Some((start, end))
+                }
+                None => Some((start, end)),
+            };
+        }
+        if let Some((merged_start, merged_end)) = merged {
+            covered += (merged_end + 1).saturating_sub(merged_start);
+        }
+    }
+    let total_length: u64 = contig_lengths.values().sum();
+    Ok(covered as f64 / total_length as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{parse_vcf_buffer_str, VCF_45};
+
+    #[test]
+    fn windows_1mb() {
+        let vars = parse_vcf_buffer_str(VCF_45);
+        let windows: Vec<(Window, Vec<Variant>)> = fixed_windows(vars, 1_000_000)
+            .map(|window| window.unwrap())
+            .collect();
+        assert_eq!(windows.len(), 2);
+        assert_eq!(
+            windows[0].0,
+            Window {
+                chrom: "20".to_string(),
+                start: 0,
+                end: 1_000_000
+            }
+        );
+        assert_eq!(windows[0].1.len(), 2);
+        assert_eq!(
+            (windows[1].0.start, windows[1].0.end),
+            (1_000_000, 2_000_000)
+        );
+        assert_eq!(windows[1].1.len(), 4);
+
+        let vcf = VCF_45.replace("\n20\t14370\t", "\n20\t0\t");
+        let windows = fixed_windows(parse_vcf_buffer_str(&vcf), 100);
+        let first = windows.map(|window| window.unwrap()).next().unwrap();
+        assert_eq!((first.0.start, first.0.end), (0, 100));
+        assert_eq!(first.1[0].pos, 0);
+    }
+
+    #[test]
+    fn distances() {
+        let vcf = VCF_45.replace("\n20\t1110696\t", "\n21\t1110696\t");
+        let distances: Vec<(String, u64)> = inter_variant_distances(parse_vcf_buffer_str(&vcf))
+            .map(|distance| distance.unwrap())
+            .collect();
+        assert_eq!(
+            distances,
+            vec![
+                ("20".to_string(), 2960),
+                ("20".to_string(), 4330),
+                ("20".to_string(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn neighbors() {
+        let vcf = VCF_45.replace("\n20\t1110696\t", "\n21\t1110696\t");
+        let positions = |vars: &[Variant]| vars.iter().map(|var| var.pos).collect::<Vec<_>>();
+        let contexts: Vec<(Vec<u64>, u64, Vec<u64>)> =
+            with_neighbors(parse_vcf_buffer_str(&vcf), 1)
+                .map(|item| {
+                    let (upstream, var, downstream) = item.unwrap();
+                    (positions(&upstream), var.pos, positions(&downstream))
+                })
+                .collect();
+        assert_eq!(
+            contexts,
+            vec![
+                (vec![], 14370, vec![17330]),
+                (vec![14370], 17330, vec![]),
+                (vec![], 1110696, vec![]),
+                (vec![], 1230237, vec![1234567]),
+                (vec![1230237], 1234567, vec![1234567]),
+                (vec![1234567], 1234567, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn span_fraction() {
+        let vcf = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1
+1\t10\t.\tAAAA\tA\t.\tPASS\t.\tGT\t0/1
+1\t12\t.\tAAAAA\tA\t.\tPASS\t.\tGT\t0/1
+1\t50\t.\tA\t<DEL>\t.\tPASS\tEND=59\tGT\t0/1
+1\t70\t.\tA\t<DEL>\t.\tPASS\tEND=5\tGT\t0/1
+1\t80\t.\tA\tG\t.\tPASS\t.\tGT\t0/1
+2\t1\t.\tA\tG\t.\tPASS\t.\tGT\t0/1
+2\t150\t.\tA\tG\t.\tPASS\t.\tGT\t0/1
+2\t160\t.\tA\tG\t.\tPASS\t.\tGT\t0/1
+3\t1\t.\tA\tG\t.\tPASS\t.\tGT\t0/1";
+        let contig_lengths = HashMap::from([("1".to_string(), 100), ("2".to_string(), 100)]);
+        let fraction =
+            variant_span_fraction(&mut parse_vcf_buffer_str(vcf), &contig_lengths).unwrap();
+        // 10-16, 50-59, 70-70, 80-80 and 1-1
+        assert_eq!(fraction, 20.0 / 200.0);
+    }
+
+    const VCF_SV: &str = "##fileformat=VCFv4.5
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1
+1\t90\tdel1\tA\t<DEL>\t.\tPASS\tSVTYPE=DEL;END=250\tGT\t0/1
+1\t120\t.\tA\tG\t.\tPASS\t.\tGT\t0/1
+1\t350\t.\tA\tG\t.\tPASS\t.\tGT\t0/1
+2\t50\t.\tA\tG\t.\tPASS\t.\tGT\t0/1";
+
+    #[test]
+    fn spanning_deletion_regions() {
+        let vars = crate::tests::parse_vcf_str(VCF_SV);
+        assert!(vars[0].in_region("1", 200, 300));
+        assert!(vars[0].in_region("1", 250, 250));
+        assert!(!vars[0].in_region("1", 251, 300));
+        assert!(!vars[0].in_region("2", 200, 300));
+        assert!(vars[1].in_region("1", 120, 120));
+        assert!(!vars[1].in_region("1", 121, 300));
+
+        let bed = std::env::temp_dir().join("nei_rs_regions.bed");
+        std::fs::write(&bed, "track name=test\n1\t199\t300\n2\t0\t10\n").unwrap();
+        let ids: Vec<(String, u64)> = filter_by_bed(parse_vcf_buffer_str(VCF_SV), &bed)
+            .unwrap()
+            .vars_iter
+            .map(|var| var.map(|var| (var.id.clone(), var.pos)).unwrap())
+            .collect();
+        assert_eq!(ids, vec![("del1".to_string(), 90)]);
+
+        std::fs::write(&bed, "1\t300\n").unwrap();
+        assert!(filter_by_bed(parse_vcf_buffer_str(VCF_SV), &bed).is_err());
+    }
+
+    #[test]
+    fn spanning_deletion_windows() {
+        let windows: Vec<(String, u64, Vec<u64>)> =
+            fixed_windows(parse_vcf_buffer_str(VCF_SV), 100)
+                .map(|window| {
+                    let (window, vars) = window.unwrap();
+                    let positions = vars.iter().map(|var| var.pos).collect();
+                    (window.chrom, window.start, positions)
+                })
+                .collect();
+        assert_eq!(
+            windows,
+            vec![
+                ("1".to_string(), 0, vec![90]),
+                ("1".to_string(), 100, vec![90, 120]),
+                ("1".to_string(), 200, vec![90]),
+                ("1".to_string(), 300, vec![350]),
+                ("2".to_string(), 0, vec![50]),
+            ]
+        );
+    }
+}
@@ -1,4 +1,8 @@
-use nei_rs::{guess_vcf_file_kind, VcfFileKind};
+use nei_rs::{
+    count_variants, estimate_collect_bytes, guess_vcf_file_kind, read_vcf_header,
+    read_vcf_with_count, sniff_reader, sniff_stream, Variant, VcfFileKind, VcfReaderBuilder,
+};
+use std::io::{Cursor, Read};
 use std::path::Path;
 
 #[test]
@@ -13,3 +17,123 @@ fn vcf_file_type() {
     let file_type = guess_vcf_file_kind(&vcf_gz_fpath).unwrap();
     assert!(file_type == VcfFileKind::GzippedVcf);
 }
+
+#[test]
+fn vcf_reader_kind() {
+    let data_dir = Path::new(file!()).parent().unwrap().join("data");
+    for (fname, kind) in [
+        ("format_example_4_5.vcf", VcfFileKind::PlainTextVcf),
+        ("format_example_4_5.vcf.gz", VcfFileKind::GzippedVcf),
+    ] {
+        let content = std::fs::read(data_dir.join(fname)).unwrap();
+        let (file_kind, mut reader) = sniff_reader(Cursor::new(content.clone())).unwrap();
+        assert_eq!(file_kind, kind);
+        let mut rewound = Vec::new();
+        reader.read_to_end(&mut rewound).unwrap();
+        assert_eq!(rewound, content);
+    }
+    assert!(sniff_reader(Cursor::new(b"<html></html>".to_vec())).is_err());
+}
+
+#[test]
+fn vcf_stream_kind() {
+    let data_dir = Path::new(file!()).parent().unwrap().join("data");
+    for (fname, kind) in [
+        ("format_example_4_5.vcf", VcfFileKind::PlainTextVcf),
+        ("format_example_4_5.vcf.gz", VcfFileKind::GzippedVcf),
+        #[cfg(feature = "zstd")]
+        ("format_example_4_5.vcf.zst", VcfFileKind::ZstdVcf),
+    ] {
+        let content = std::fs::read(data_dir.join(fname)).unwrap();
+        // The first read only returns one byte.
+        let stream = Cursor::new(&content[..1]).chain(Cursor::new(&content[1..]));
+        let (file_kind, mut reader) = sniff_stream(stream, fname).unwrap();
+        assert_eq!(file_kind, kind);
+        let mut streamed = Vec::new();
+        reader.read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed, content);
+    }
+    assert!(sniff_stream(Cursor::new(b"<".to_vec()), "html").is_err());
+}
+
+#[test]
+fn vcf_header() {
+    let data_dir = Path::new(file!()).parent().unwrap().join("data");
+    for fname in ["format_example_4_5.vcf", "format_example_4_5.vcf.gz"] {
+        let header = read_vcf_header(&data_dir.join(fname)).unwrap();
+        assert_eq!(header.samples, vec!["NA00001", "NA00002", "NA00003"]);
+        assert_eq!(header.contigs[0].length, Some(62435964));
+    }
+}
+
+#[test]
+fn vcf_collect_size() {
+    let data_dir = Path::new(file!()).parent().unwrap().join("data");
+    let fpath = data_dir.join("format_example_4_5.vcf.gz");
+    assert_eq!(count_variants(&fpath).unwrap(), 6);
+    let n_bytes = estimate_collect_bytes(&fpath).unwrap();
+    assert!(n_bytes > 6 * std::mem::size_of::<Variant>() as u64);
+}
+
+#[test]
+fn vcf_with_count() {
+    let data_dir = Path::new(file!()).parent().unwrap().join("data");
+    let (n_vars, vars) = read_vcf_with_count(&data_dir.join("format_example_4_5.vcf.gz")).unwrap();
+    assert_eq!(n_vars, 6);
+    assert_eq!(vars.vars_iter.count(), 6);
+}
+
+#[test]
+fn vcf_small_buffer() {
+    let data_dir = Path::new(file!()).parent().unwrap().join("data");
+    for fname in ["format_example_4_5.vcf", "format_example_4_5.vcf.gz"] {
+        let vars = VcfReaderBuilder::new()
+            .buffer_capacity(16)
+            .read_file(&data_dir.join(fname))
+            .unwrap();
+        let vars: Vec<Variant> = vars.vars_iter.map(|var| var.unwrap()).collect();
+        assert_eq!(vars.len(), 6);
+    }
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn vcf_zstd() {
+    let data_dir = Path::new(file!()).parent().unwrap().join("data");
+    let zstd_fpath = data_dir.join("format_example_4_5.vcf.zst");
+    assert_eq!(
+        guess_vcf_file_kind(&zstd_fpath).unwrap(),
+        VcfFileKind::ZstdVcf
+    );
+    let lines = |fname: &str| -> Vec<String> {
+        VcfReaderBuilder::new()
+            .read_file(&data_dir.join(fname))
+            .unwrap()
+            .vars_iter
+            .map(|var| var.unwrap().to_vcf_line("GT"))
+            .collect()
+    };
+    let zstd_lines = lines("format_example_4_5.vcf.zst");
+    assert_eq!(zstd_lines.len(), 6);
+    assert_eq!(zstd_lines, lines("format_example_4_5.vcf"));
+}
+
+#[test]
+fn variant_accessors() {
+    let data_dir = Path::new(file!()).parent().unwrap().join("data");
+    let vars = VcfReaderBuilder::new()
+        .read_file(&data_dir.join("format_example_4_5.vcf"))
+        .unwrap();
+    let vars: Vec<Variant> = vars.vars_iter.map(|var| var.unwrap()).collect();
+    assert_eq!(vars[0].chrom(), "20");
+    assert_eq!(vars[0].pos(), 14370);
+    assert_eq!(vars[0].id(), "rs6054257");
+    assert_eq!(vars[0].alleles(), ["G", "A"]);
+    assert_eq!(vars[0].qual(), 29.0);
+    assert!(vars[0].filters().is_empty());
+    assert_eq!(vars[0].genotypes(), [vec![0, 0], vec![1, 0], vec![1, 1]]);
+    assert_eq!(vars[0].ploidy(), 2);
+    assert_eq!(vars[1].filters(), ["q10"]);
+    assert_eq!(vars[1].id(), ".");
+    assert_eq!(vars[4].alleles(), ["GTC", "G", "GTCT"]);
+}